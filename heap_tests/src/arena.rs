@@ -0,0 +1,62 @@
+//Un tas de test pris dans un Vec<u8> de l'hote, a la place de la region HEAP_START du noyau.
+
+/// Alignment of the start of an arena, the same as a page of the kernel heap.
+pub const ARENA_ALIGN: usize = 4096;
+
+/// A heap region backed by host memory.
+pub struct Arena {
+    memory: Vec<u8>,
+    offset: usize,
+    size: usize,
+}
+
+impl Arena {
+    /// Allocates a zeroed arena of `size` bytes, starting on a page boundary.
+    pub fn new(size: usize) -> Self {
+        let memory = vec![0u8; size + ARENA_ALIGN];
+        let offset = super::align_up(memory.as_ptr() as usize, ARENA_ALIGN) - memory.as_ptr() as usize;
+        Arena {
+            memory,
+            offset,
+            size,
+        }
+    }
+
+    /// Returns the start address of the arena.
+    pub fn start(&mut self) -> usize {
+        unsafe { self.memory.as_mut_ptr().add(self.offset) as usize }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether `[addr, addr + size)` lies within the arena.
+    pub fn contains(&mut self, addr: usize, size: usize) -> bool {
+        let start = self.start();
+        addr >= start && addr + size <= start + self.size
+    }
+}
+
+/// A xorshift pseudo random number generator, so that every run of a fuzz
+/// test with the same seed does the same allocations.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // l'etat ne doit jamais valoir 0
+        Rng(seed | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
@@ -0,0 +1,45 @@
+//Ce crate joue le role du module allocator du noyau : les fichiers de ../src/allocator/ sont inclus tels quels, leurs
+//super::align_up et super::HeapAllocator pointent donc ici. Seuls les allocateurs qui ne touchent pas au materiel sont inclus.
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::test_runner)]
+
+extern crate alloc;
+
+#[path = "../../src/allocator/heap_allocator.rs"]
+mod heap_allocator;
+pub use heap_allocator::HeapAllocator;
+use heap_allocator::align_up;
+
+#[path = "../../src/allocator/bump.rs"]
+pub mod bump;
+#[path = "../../src/allocator/fixed_size_block.rs"]
+pub mod fixed_size_block;
+#[path = "../../src/allocator/linked_list.rs"]
+pub mod linked_list;
+#[path = "../../src/allocator/stats.rs"]
+pub mod stats;
+
+pub mod arena;
+
+//meme lanceur que celui du noyau, pour executer aussi les #[test_case] des fichiers inclus
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T> Testable for T
+where
+    T: Fn(),
+{
+    fn run(&self) {
+        print!("{}...\t", core::any::type_name::<T>());
+        self();
+        println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+}
@@ -0,0 +1,161 @@
+#![feature(custom_test_frameworks)]
+#![test_runner(heap_tests::test_runner)]
+//Sequences aleatoires d'allocations et de liberations sur chaque allocateur. A chaque pas on verifie que les blocs sont alignes,
+//dans le tas et ne se chevauchent pas, et que le contenu des blocs vivants n'a pas ete ecrase. Une fois tout libere, il ne
+//doit rien rester d'alloue.
+//cargo +nightly test --manifest-path heap_tests/Cargo.toml --test fuzz (hors du depot, voir heap_tests/Cargo.toml)
+
+use core::alloc::Layout;
+use core::ptr;
+use heap_tests::arena::{Arena, Rng};
+use heap_tests::bump::BumpAllocator;
+use heap_tests::fixed_size_block::FixedSizeBlockAllocator;
+use heap_tests::linked_list::{FitStrategy, LinkedListAllocator};
+use heap_tests::HeapAllocator;
+
+const ARENA_SIZE: usize = 256 * 1024;
+/// The heap starts with a part of the arena and grows by this much when full.
+const GROWTH_STEP: usize = 16 * 1024;
+const STEPS: usize = 2000;
+const SEEDS: u64 = 16;
+
+/// A live allocation and the byte it was filled with.
+struct Block {
+    addr: usize,
+    layout: Layout,
+    pattern: u8,
+}
+
+impl Block {
+    fn end(&self) -> usize {
+        self.addr + self.layout.size()
+    }
+
+    fn check_pattern(&self) {
+        let memory = unsafe { core::slice::from_raw_parts(self.addr as *const u8, self.layout.size()) };
+        assert!(
+            memory.iter().all(|&byte| byte == self.pattern),
+            "live block at {:#x} ({:?}) was overwritten",
+            self.addr,
+            self.layout
+        );
+    }
+}
+
+fn random_layout(rng: &mut Rng) -> Layout {
+    // surtout des petits blocs, parfois assez grands pour passer par l'allocateur de secours
+    let size = match rng.below(8) {
+        0 => 1 + rng.below(4096),
+        _ => 1 + rng.below(256),
+    };
+    let align = match rng.below(16) {
+        0 => 4096,
+        _ => 1 << rng.below(7),
+    };
+    Layout::from_size_align(size, align).unwrap()
+}
+
+fn free_block<A: HeapAllocator>(allocator: &mut A, block: Block) {
+    block.check_pattern();
+    unsafe { allocator.deallocate(block.addr as *mut u8, block.layout) };
+}
+
+/// Runs a random sequence on a fresh allocator and returns it, with all blocks
+/// freed, together with the final heap size.
+fn fuzz<A: HeapAllocator>(mut allocator: A, seed: u64) -> (A, usize) {
+    let mut arena = Arena::new(ARENA_SIZE);
+    let heap_start = arena.start();
+    let mut heap_size = ARENA_SIZE / 4;
+    unsafe { allocator.init(heap_start, heap_size) };
+
+    let mut rng = Rng::new(seed);
+    let mut live: Vec<Block> = Vec::new();
+    for _ in 0..STEPS {
+        if !live.is_empty() && rng.below(3) == 0 {
+            let block = live.swap_remove(rng.below(live.len()));
+            free_block(&mut allocator, block);
+            continue;
+        }
+
+        let layout = random_layout(&mut rng);
+        let mut ptr = allocator.allocate(layout);
+        // comme grow_heap dans le noyau
+        while ptr.is_null() && heap_size < arena.size() {
+            let by = GROWTH_STEP.min(arena.size() - heap_size);
+            unsafe { allocator.extend(by) };
+            heap_size += by;
+            ptr = allocator.allocate(layout);
+        }
+        if ptr.is_null() {
+            continue; // l'arene est pleine
+        }
+
+        let block = Block {
+            addr: ptr as usize,
+            layout,
+            pattern: rng.next_u64() as u8,
+        };
+        assert_eq!(block.addr % layout.align(), 0, "misaligned block for {:?}", layout);
+        assert!(
+            arena.contains(block.addr, layout.size()) && block.end() <= heap_start + heap_size,
+            "block at {:#x} ({:?}) outside of the heap",
+            block.addr,
+            layout
+        );
+        for other in &live {
+            assert!(
+                block.end() <= other.addr || other.end() <= block.addr,
+                "block at {:#x} ({:?}) overlaps block at {:#x} ({:?})",
+                block.addr,
+                layout,
+                other.addr,
+                other.layout
+            );
+        }
+        unsafe { ptr::write_bytes(ptr, block.pattern, layout.size()) };
+        live.push(block);
+    }
+
+    while !live.is_empty() {
+        let block = live.swap_remove(rng.below(live.len()));
+        free_block(&mut allocator, block);
+    }
+    (allocator, heap_size)
+}
+
+#[test_case]
+fn fuzz_bump() {
+    for seed in 0..SEEDS {
+        let (allocator, heap_size) = fuzz(BumpAllocator::new(), seed);
+        // le tas est remis a zero quand la derniere allocation est liberee
+        assert_eq!(allocator.largest_free_block(), Some(heap_size), "seed {}", seed);
+    }
+}
+
+#[test_case]
+fn fuzz_linked_list() {
+    for strategy in [FitStrategy::FirstFit, FitStrategy::BestFit] {
+        for seed in 0..SEEDS {
+            let (allocator, heap_size) = fuzz(LinkedListAllocator::with_strategy(strategy), seed);
+            // toutes les regions libres ont ete fusionnees en une seule
+            assert_eq!(
+                allocator.largest_free_block(),
+                Some(heap_size),
+                "{:?}, seed {}",
+                strategy,
+                seed
+            );
+        }
+    }
+}
+
+#[test_case]
+fn fuzz_fixed_size_block() {
+    for seed in 0..SEEDS {
+        let (allocator, _) = fuzz(FixedSizeBlockAllocator::new(), seed);
+        // les blocs liberes restent dans les listes, mais aucun ne doit etre encore compte comme utilise
+        for class in allocator.size_classes().unwrap() {
+            assert_eq!(class.in_use, 0, "{} byte blocks, seed {}", class.block_size, seed);
+        }
+    }
+}
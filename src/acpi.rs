@@ -0,0 +1,418 @@
+//Lecture des tables ACPI fournies par le BIOS. Le RSDP (Root System Description Pointer) se trouve dans les 1 premiers KiB de
+//l'EBDA ou entre 0xE0000 et 0xFFFFF, aligne sur 16 octets ; il donne l'adresse de la RSDT (adresses sur 32 bits) et, depuis ACPI 2.0,
+//celle de la XSDT (adresses sur 64 bits), qui listent les autres tables par leur signature. Chaque structure porte une somme de
+//controle : la somme de ses octets vaut 0 modulo 256, une table dont la somme est fausse est ignoree.
+//Sont decodees la MADT ("APIC", controleurs d'interruption), la FADT ("FACP", ports de gestion de l'alimentation) et la table HPET.
+//Les tables sont lues a travers le mappage de toute la memoire physique, memory::init doit donc avoir ete appele. Elles sont dans
+//des regions AcpiReclaimable que le noyau recupere au demarrage : init les decode une fois pour toutes avant cette recuperation.
+
+use crate::memory;
+use core::ptr;
+use x86_64::PhysAddr;
+
+/// Maximal number of I/O APICs, interrupt source overrides and local APICs
+/// taken from the MADT.
+pub const MAX_IO_APICS: usize = 4;
+pub const MAX_OVERRIDES: usize = 16;
+pub const MAX_LOCAL_APICS: usize = 16;
+
+/// Size of the header shared by every system description table.
+const SDT_HEADER_SIZE: u64 = 36;
+
+/// An error returned by `init`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// No RSDP was found in the BIOS areas.
+    NoRsdp,
+    /// The checksum of the RSDP at this address is wrong.
+    InvalidRsdp(PhysAddr),
+    /// The RSDT or XSDT at this address is missing or its checksum is wrong.
+    InvalidRootTable(PhysAddr),
+}
+
+/// Reads a `T` at physical address `addr`.
+fn read_phys<T: Copy>(addr: u64) -> T {
+    let offset = memory::physical_memory_offset().expect("memory::init was not called");
+    unsafe { ptr::read_unaligned((offset + addr).as_ptr::<T>()) }
+}
+
+/// Returns whether the `length` bytes at `addr` add up to 0.
+fn checksum_ok(addr: u64, length: u64) -> bool {
+    (addr..addr + length).fold(0u8, |sum, byte| sum.wrapping_add(read_phys(byte))) == 0
+}
+
+/// The Root System Description Pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rsdp {
+    pub address: PhysAddr,
+    /// 0 for ACPI 1.0, 2 and above for ACPI 2.0 and later.
+    pub revision: u8,
+    pub rsdt: PhysAddr,
+    /// Only given since ACPI 2.0.
+    pub xsdt: Option<PhysAddr>,
+}
+
+impl Rsdp {
+    /// Decodes the RSDP at `addr` and checks its checksums.
+    fn read(addr: u64) -> Result<Self, AcpiError> {
+        let invalid = AcpiError::InvalidRsdp(PhysAddr::new(addr));
+        // les 20 premiers octets (ACPI 1.0) ont leur propre somme, la structure etendue une seconde
+        if !checksum_ok(addr, 20) {
+            return Err(invalid);
+        }
+        let revision = read_phys::<u8>(addr + 15);
+        let xsdt = if revision >= 2 {
+            let length = u64::from(read_phys::<u32>(addr + 20));
+            if length < 36 || !checksum_ok(addr, length) {
+                return Err(invalid);
+            }
+            Some(read_phys::<u64>(addr + 24)).filter(|&xsdt| xsdt != 0).map(PhysAddr::new)
+        } else {
+            None
+        };
+        Ok(Rsdp {
+            address: PhysAddr::new(addr),
+            revision,
+            rsdt: PhysAddr::new(u64::from(read_phys::<u32>(addr + 16))),
+            xsdt,
+        })
+    }
+
+    /// Returns the XSDT if there is one, the RSDT otherwise, and the size of
+    /// its entries.
+    fn root_table(&self) -> (u64, u64) {
+        match self.xsdt {
+            Some(xsdt) => (xsdt.as_u64(), 8),
+            None => (self.rsdt.as_u64(), 4),
+        }
+    }
+}
+
+/// Returns the address of the RSDP in the memory range `start..end`.
+fn scan_for_rsdp(start: u64, end: u64) -> Option<u64> {
+    (start..end)
+        .step_by(16)
+        .find(|&addr| read_phys::<[u8; 8]>(addr) == *b"RSD PTR ")
+}
+
+/// Looks for the RSDP in the EBDA, then in the BIOS read-only area, and
+/// checks it.
+pub fn find_rsdp() -> Result<Rsdp, AcpiError> {
+    // le segment de l'EBDA est stocke a l'adresse 0x40E de la BIOS Data Area
+    let ebda = u64::from(read_phys::<u16>(0x40e)) << 4;
+    let in_ebda = if ebda != 0 { scan_for_rsdp(ebda, ebda + 1024) } else { None };
+    let addr = in_ebda
+        .or_else(|| scan_for_rsdp(0xe0000, 0x100000))
+        .ok_or(AcpiError::NoRsdp)?;
+    Rsdp::read(addr)
+}
+
+/// Returns the length of the table at `addr` if its checksum is right.
+fn checked_table(addr: u64) -> Option<u64> {
+    let length = u64::from(read_phys::<u32>(addr + 4));
+    Some(length).filter(|&length| length >= SDT_HEADER_SIZE && checksum_ok(addr, length))
+}
+
+/// Returns an iterator over the signatures and addresses of the tables listed
+/// in the root table of `rsdp`, skipping those with a wrong checksum.
+pub fn tables(rsdp: &Rsdp) -> Result<impl Iterator<Item = ([u8; 4], PhysAddr)>, AcpiError> {
+    let (root, entry_size) = rsdp.root_table();
+    let length = checked_table(root).ok_or(AcpiError::InvalidRootTable(PhysAddr::new(root)))?;
+    let entries = (length - SDT_HEADER_SIZE) / entry_size;
+    Ok((0..entries)
+        .map(move |index| {
+            let entry = root + SDT_HEADER_SIZE + index * entry_size;
+            match entry_size {
+                8 => read_phys::<u64>(entry),
+                _ => u64::from(read_phys::<u32>(entry)),
+            }
+        })
+        .filter(|&table| table != 0 && checked_table(table).is_some())
+        .map(|table| (read_phys::<[u8; 4]>(table), PhysAddr::new(table))))
+}
+
+/// Returns the physical address of the first valid table with the given
+/// signature.
+pub fn find_table(signature: &[u8; 4]) -> Option<PhysAddr> {
+    let rsdp = find_rsdp().ok()?;
+    tables(&rsdp)
+        .ok()?
+        .find(|(found, _)| found == signature)
+        .map(|(_, table)| table)
+}
+
+/// An I/O APIC described by the MADT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// The first global system interrupt handled by this I/O APIC.
+    pub gsi_base: u32,
+}
+
+/// An ISA interrupt that is not connected to the global system interrupt
+/// with the same number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptOverride {
+    pub source: u8,
+    pub gsi: u32,
+    /// MPS INTI flags: bits 0-1 polarity, bits 2-3 trigger mode.
+    pub flags: u16,
+}
+
+impl InterruptOverride {
+    /// Returns whether the interrupt is active low (polarity 3).
+    pub fn active_low(&self) -> bool {
+        self.flags & 0b11 == 0b11
+    }
+
+    /// Returns whether the interrupt is level triggered (trigger mode 3).
+    pub fn level_triggered(&self) -> bool {
+        (self.flags >> 2) & 0b11 == 0b11
+    }
+}
+
+/// The interrupt controllers described by the MADT.
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    /// Whether the system also has the two legacy 8259 PICs.
+    pub pcat_compat: bool,
+    /// APIC ids of the enabled processors.
+    pub local_apic_ids: [Option<u8>; MAX_LOCAL_APICS],
+    pub io_apics: [Option<IoApicInfo>; MAX_IO_APICS],
+    pub overrides: [Option<InterruptOverride>; MAX_OVERRIDES],
+}
+
+impl Madt {
+    pub fn io_apics(&self) -> impl Iterator<Item = &IoApicInfo> {
+        self.io_apics.iter().flatten()
+    }
+
+    pub fn overrides(&self) -> impl Iterator<Item = &InterruptOverride> {
+        self.overrides.iter().flatten()
+    }
+
+    /// Returns the global system interrupt of ISA interrupt `irq` and its
+    /// override, if any.
+    pub fn isa_irq(&self, irq: u8) -> (u32, Option<InterruptOverride>) {
+        match self.overrides().find(|o| o.source == irq) {
+            Some(&o) => (o.gsi, Some(o)),
+            None => (u32::from(irq), None),
+        }
+    }
+}
+
+/// Stores `value` in the first free slot of `slots`; entries that do not fit
+/// are dropped.
+fn push<T>(slots: &mut [Option<T>], value: T) {
+    if let Some(slot) = slots.iter_mut().find(|slot| slot.is_none()) {
+        *slot = Some(value);
+    }
+}
+
+/// Decodes the MADT at `table`.
+fn parse_madt(table: u64) -> Madt {
+    let length = u64::from(read_phys::<u32>(table + 4));
+    let mut madt = Madt {
+        local_apic_address: PhysAddr::new(u64::from(read_phys::<u32>(table + 36))),
+        pcat_compat: read_phys::<u32>(table + 40) & 1 != 0,
+        local_apic_ids: [None; MAX_LOCAL_APICS],
+        io_apics: [None; MAX_IO_APICS],
+        overrides: [None; MAX_OVERRIDES],
+    };
+
+    // les entrees suivent l'en-tete et les deux champs ci-dessus ; chacune commence par son type et sa longueur
+    let mut entry = table + 44;
+    while entry + 2 <= table + length {
+        let (kind, entry_length) = (read_phys::<u8>(entry), u64::from(read_phys::<u8>(entry + 1)));
+        // une longueur nulle bouclerait sur place, une entree qui depasse la table serait lue hors de celle-ci
+        if entry_length < 2 || entry + entry_length > table + length {
+            break;
+        }
+        // une entree plus courte que la structure de son type est ignoree
+        let minimal_length = match kind {
+            0 => 8,
+            1 | 5 => 12,
+            2 => 10,
+            _ => 2,
+        };
+        if entry_length < minimal_length {
+            entry += entry_length;
+            continue;
+        }
+        match kind {
+            // processeur : le bit 0 des drapeaux indique s'il est utilisable
+            0 if read_phys::<u32>(entry + 4) & 1 != 0 => {
+                push(&mut madt.local_apic_ids, read_phys::<u8>(entry + 3))
+            }
+            1 => push(
+                &mut madt.io_apics,
+                IoApicInfo {
+                    id: read_phys(entry + 2),
+                    address: PhysAddr::new(u64::from(read_phys::<u32>(entry + 4))),
+                    gsi_base: read_phys(entry + 8),
+                },
+            ),
+            2 => push(
+                &mut madt.overrides,
+                InterruptOverride {
+                    source: read_phys(entry + 3),
+                    gsi: read_phys(entry + 4),
+                    flags: read_phys(entry + 8),
+                },
+            ),
+            // adresse 64 bits du local APIC, remplace celle de l'en-tete
+            5 => madt.local_apic_address = PhysAddr::new(read_phys(entry + 4)),
+            _ => {}
+        }
+        entry += entry_length;
+    }
+    madt
+}
+
+/// The power management ports described by the FADT.
+///
+/// Only the 32 bit fields of ACPI 1.0 are decoded, they give I/O ports on
+/// x86. A port is `None` if the machine does not have it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fadt {
+    /// The table with the AML code of the machine, not decoded.
+    pub dsdt: PhysAddr,
+    /// The interrupt used for ACPI events, in the ISA numbering of the 8259.
+    pub sci_interrupt: u16,
+    /// The port to which `acpi_enable` or `acpi_disable` is written to give
+    /// the power management to the OS or back to the firmware.
+    pub smi_command_port: Option<u16>,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event_block: Option<u16>,
+    pub pm1b_event_block: Option<u16>,
+    pub pm1a_control_block: Option<u16>,
+    pub pm1b_control_block: Option<u16>,
+    /// The ACPI power management timer, counting at 3.579545 MHz.
+    pub pm_timer_block: Option<u16>,
+    /// Whether the power management timer counts on 32 bits rather than 24.
+    pub pm_timer_32bit: bool,
+    /// Index of the century in the CMOS RTC, 0 if it has none.
+    pub century: u8,
+}
+
+/// Returns the I/O port stored in the 32 bit field at `addr`, if not 0.
+fn read_port(addr: u64) -> Option<u16> {
+    Some(read_phys::<u32>(addr)).filter(|&port| port != 0).map(|port| port as u16)
+}
+
+/// Decodes the FADT at `table`.
+fn parse_fadt(table: u64) -> Fadt {
+    Fadt {
+        dsdt: PhysAddr::new(u64::from(read_phys::<u32>(table + 40))),
+        sci_interrupt: read_phys(table + 46),
+        smi_command_port: read_port(table + 48),
+        acpi_enable: read_phys(table + 52),
+        acpi_disable: read_phys(table + 53),
+        pm1a_event_block: read_port(table + 56),
+        pm1b_event_block: read_port(table + 60),
+        pm1a_control_block: read_port(table + 64),
+        pm1b_control_block: read_port(table + 68),
+        pm_timer_block: read_port(table + 76),
+        // drapeau TMR_VAL_EXT
+        pm_timer_32bit: read_phys::<u32>(table + 112) & (1 << 8) != 0,
+        century: read_phys(table + 108),
+    }
+}
+
+/// The High Precision Event Timer described by the HPET table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hpet {
+    /// Physical address of the timer's registers.
+    pub address: PhysAddr,
+    /// Sequence number of this timer block.
+    pub number: u8,
+    pub vendor_id: u16,
+    /// Number of comparators of the timer block.
+    pub comparators: u8,
+    pub counter_64bit: bool,
+    /// Minimal period of the periodic mode, in ticks of the main counter.
+    pub minimum_tick: u16,
+}
+
+/// Decodes the HPET table at `table`.
+fn parse_hpet(table: u64) -> Hpet {
+    let block_id = read_phys::<u32>(table + 36);
+    Hpet {
+        // adresse generique (GAS) : l'adresse 64 bits suit l'espace d'adressage, la largeur et le decalage
+        address: PhysAddr::new(read_phys(table + 44)),
+        number: read_phys(table + 52),
+        vendor_id: (block_id >> 16) as u16,
+        comparators: ((block_id >> 8) & 0x1f) as u8 + 1,
+        counter_64bit: block_id & (1 << 13) != 0,
+        minimum_tick: read_phys(table + 53),
+    }
+}
+
+/// The tables decoded by `init`.
+#[derive(Debug, Clone, Copy)]
+pub struct AcpiTables {
+    pub rsdp: Rsdp,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+}
+
+static TABLES: spin::Mutex<Option<AcpiTables>> = spin::Mutex::new(None);
+
+/// Finds and decodes the ACPI tables, and keeps them for `tables`, `madt`,
+/// `fadt` and `hpet`.
+///
+/// Must be called before the `AcpiReclaimable` regions are given to the
+/// frame allocator.
+pub fn init() -> Result<AcpiTables, AcpiError> {
+    let rsdp = find_rsdp()?;
+    let mut tables = AcpiTables {
+        rsdp,
+        madt: None,
+        fadt: None,
+        hpet: None,
+    };
+    for (signature, table) in self::tables(&rsdp)? {
+        let table = table.as_u64();
+        match &signature {
+            b"APIC" if tables.madt.is_none() => tables.madt = Some(parse_madt(table)),
+            b"FACP" if tables.fadt.is_none() => tables.fadt = Some(parse_fadt(table)),
+            b"HPET" if tables.hpet.is_none() => tables.hpet = Some(parse_hpet(table)),
+            _ => {}
+        }
+    }
+    *TABLES.lock() = Some(tables);
+    Ok(tables)
+}
+
+/// Returns the tables decoded by `init`, `None` if it was not called.
+pub fn decoded_tables() -> Option<AcpiTables> {
+    *TABLES.lock()
+}
+
+/// Returns the table kept by `init`, or decodes it from memory if `init` was
+/// not called.
+fn decoded<T>(kept: fn(&AcpiTables) -> Option<T>, signature: &[u8; 4], parse: fn(u64) -> T) -> Option<T> {
+    match decoded_tables() {
+        Some(tables) => kept(&tables),
+        None => find_table(signature).map(|table| parse(table.as_u64())),
+    }
+}
+
+/// Returns the MADT.
+pub fn madt() -> Option<Madt> {
+    decoded(|tables| tables.madt, b"APIC", parse_madt)
+}
+
+/// Returns the FADT.
+pub fn fadt() -> Option<Fadt> {
+    decoded(|tables| tables.fadt, b"FACP", parse_fadt)
+}
+
+/// Returns the HPET table.
+pub fn hpet() -> Option<Hpet> {
+    decoded(|tables| tables.hpet, b"HPET", parse_hpet)
+}
@@ -1,6 +1,7 @@
 
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::ptr::null_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use x86_64::{
     structures::paging::{
@@ -8,10 +9,37 @@ use x86_64::{
     },
     VirtAddr,
 };
-use linked_list_allocator::LockedHeap;
+use linked_list_allocator::Heap;
+use crate::memory::{self, vmm::RegionKind};
 
-#[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+//L'allocateur global est choisi avec une feature cargo (alloc_linked_list par defaut : contrairement a linked_list_allocator, il
+//peut donner son plus grand bloc libre aux statistiques), par exemple :
+//cargo test --test heap_allocation --no-default-features --features alloc_fixed_size_block
+#[cfg(any(
+    all(
+        feature = "alloc_locked_heap",
+        any(feature = "alloc_bump", feature = "alloc_linked_list", feature = "alloc_fixed_size_block")
+    ),
+    all(
+        feature = "alloc_bump",
+        any(feature = "alloc_linked_list", feature = "alloc_fixed_size_block")
+    ),
+    all(feature = "alloc_linked_list", feature = "alloc_fixed_size_block"),
+))]
+compile_error!("the alloc_* features are mutually exclusive, enable only one of them");
+
+#[cfg(not(any(
+    feature = "alloc_locked_heap",
+    feature = "alloc_bump",
+    feature = "alloc_linked_list",
+    feature = "alloc_fixed_size_block"
+)))]
+compile_error!("no global allocator selected, enable one of the alloc_* features");
+
+//Le verrou du tas vient en dernier, apres KERNEL_MEMORY et le VMM : il est relache avant que grow_heap ne prenne
+//KERNEL_MEMORY, et n'est tenu qu'interruptions desactivees, pour qu'un gestionnaire d'interruption puisse allouer.
+#[cfg(feature = "alloc_locked_heap")]
+static HEAP: Locked<Heap> = Locked::new(Heap::empty());
 
 pub struct Dummy;
 //pour utiliser lalocator lined au lieu de fixed_size_block
@@ -19,41 +47,104 @@ pub mod fixed_size_block;
 //Bien que l'approche par bloc de taille fixe ait de bien meilleures performances que l'approche par liste chaînée, elle gaspille jusqu'à la moitié de 
 //la mémoire lors de l'utilisation de puissances de 2 comme tailles de bloc. La valeur de ce compromis dépend fortement du type d'application.
 // Pour un noyau de système d'exploitation, où les performances sont essentielles, l'approche par bloc de taille fixe semble être le meilleur choix.
-/*
-use fixed_size_block::FixedSizeBlockAllocator;
-#[global_allocator]
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(
-    FixedSizeBlockAllocator::new());
-*/
+#[cfg(feature = "alloc_fixed_size_block")]
+static HEAP: Locked<fixed_size_block::FixedSizeBlockAllocator> =
+    Locked::new(fixed_size_block::FixedSizeBlockAllocator::new());
 
 //pour utiliser lalocator lined au lieu de linked_list_allocator 
 pub mod linked_list;
-/*
-use linked_list::LinkedListAllocator;
-#[global_allocator]
-static ALLOCATOR: Locked<LinkedListAllocator> =
-    Locked::new(LinkedListAllocator::new());
-*/
+#[cfg(feature = "alloc_linked_list")]
+static HEAP: Locked<linked_list::LinkedListAllocator> =
+    Locked::new(linked_list::LinkedListAllocator::new());
 
 //pour utiliser lalocator bump au lieu de linked_list_allocator 
 pub mod bump;
-
-/*
+//caches d'objets de taille fixe, construits au-dessus de l'allocateur global
+pub mod slab;
+//statistiques d'utilisation du tas
+pub mod stats;
+//mode de debogage du tas (zones de garde, empoisonnement, double liberation)
+pub mod debug;
+//recuperation de memoire et allocations faillibles quand le tas est plein
+pub mod oom;
+//interface commune des allocateurs, sans dependance au noyau pour pouvoir aussi les tester sur l'hote (voir heap_tests/)
+mod heap_allocator;
+pub use heap_allocator::HeapAllocator;
+use heap_allocator::align_up;
+use stats::{HeapCounters, HeapStats};
 //point fort rapide mais inconvenient : une seule allocation de longue durée suffit pour empecher la éutilisation de la memoir
-use bump::BumpAllocator;
+#[cfg(feature = "alloc_bump")]
+static HEAP: Locked<bump::BumpAllocator> = Locked::new(bump::BumpAllocator::new());
+
+//Avec la feature heap_debug, l'allocateur choisi ci-dessus est enveloppe dans debug::DebugAllocator, par exemple :
+//cargo test --test heap_allocation --features heap_debug
+#[cfg(not(feature = "heap_debug"))]
+#[global_allocator]
+static ALLOCATOR: Counted<KernelAllocator> = Counted(KernelAllocator);
 
+#[cfg(feature = "heap_debug")]
 #[global_allocator]
-static ALLOCATOR: Locked<BumpAllocator> = Locked::new(BumpAllocator::new());
-*/
+static ALLOCATOR: Counted<debug::DebugAllocator<KernelAllocator>> =
+    Counted(debug::DebugAllocator::new(KernelAllocator));
+
+/// Updates the counters of `HEAP` with the layouts of the callers, and not
+/// with the larger blocks a wrapper such as `DebugAllocator` asks for.
+struct Counted<A>(A);
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Counted<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.0.alloc(layout);
+        if !ptr.is_null() {
+            HEAP.counters.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout);
+        HEAP.counters.record_dealloc(layout.size());
+    }
+}
+
+/// Forwards to the heap allocator selected by the `alloc_*` feature.
+struct KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        loop {
+            let ptr = HEAP.alloc(layout);
+            // on reessaie tant que les fonctions de recuperation liberent quelque chose
+            if !ptr.is_null() || oom::reclaim(layout.size()) == 0 {
+                return ptr;
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        HEAP.dealloc(ptr, layout)
+    }
+}
 
 //La fonction prend des références mutables à a Mapperet à une FrameAllocatorinstance, toutes deux limitées à des pages de 4 Ko en utilisant Size4KiBcomme paramètre générique. La valeur de retour de la fonction est a Resultavec le type d'unité ()comme variante de succès et a MapToErrorcomme variante d'erreur, qui est le type d'erreur renvoyé par la Mapper::map_tométhode. La réutilisation du type d'erreur a ici du sens car la map_tométhode est la principale source d'erreurs dans cette fonction.
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
+    //la plage virtuelle du tas (jusqu'a HEAP_MAX_SIZE, pour qu'il puisse grandir sans bouger) vient du gestionnaire d'adresses virtuelles.
+    //Elle n'est pas paresseuse : seul grow_heap mappe la suite du tas, un acces au-dela de HEAP_MAPPED provoque donc une page fault.
+    let region = memory::vmm::with_vmm(|vmm| {
+        vmm.allocate(
+            HEAP_MAX_SIZE as u64,
+            RegionKind::Heap,
+            HEAP_FLAGS,
+        )
+    })
+    .expect("no virtual address space left for the heap");
+    let heap_start = region.start.as_u64() as usize;
+
     //Nous allouons un cadre physique auquel la page doit être mappée à l'aide de la FrameAllocator::allocate_frameméthode. Cette méthode revient Nonelorsqu'il ne reste plus d'images. Nous traitons ce cas en le mappant à une MapToError::FrameAllocationFailederreur via la Option::ok_orméthode, puis appliquons l' opérateur de point d'interrogation pour revenir tôt en cas d'erreur.
     let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
+        let heap_start = region.start;
         let heap_end = heap_start + HEAP_SIZE - 1u64;
         let heap_start_page = Page::containing_address(heap_start);
         let heap_end_page = Page::containing_address(heap_end);
@@ -65,7 +156,7 @@ pub fn init_heap(
             .allocate_frame()
             .ok_or(MapToError::FrameAllocationFailed)?;
             //Nous définissons le PRESENTdrapeau requis et le WRITABLEdrapeau de la page. Avec ces drapeaux, les accès en lecture et en écriture sont autorisés, ce qui est logique pour la mémoire de tas.
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let flags = HEAP_FLAGS;
         unsafe {
             //Nous utilisons la Mapper::map_tométhode de création du mappage dans la table des pages actives. La méthode peut échouer, nous utilisons donc à nouveau l' opérateur de point d'interrogation pour transmettre l'erreur à l'appelant. En cas de succès, la méthode renvoie une MapperFlushinstance que nous pouvons utiliser pour mettre à jour le tampon de recherche de traduction à l'aide de la flushméthode.
             mapper.map_to(page, frame, flags, frame_allocator)?.flush()
@@ -74,16 +165,93 @@ pub fn init_heap(
     //il renvoie toujours une erreur sur alloc. Pour résoudre ce problème, nous devons initialiser l'allocateur après avoir créé le tas
     //Nous utilisons la lockméthode sur le spinlock interne du LockedHeaptype pour obtenir une référence exclusive à l' Heapinstance enveloppée, sur laquelle nous appelons ensuite la initméthode avec les limites du tas comme arguments. Il est important que nous initialisions le tas après avoir mappé les pages du tas, car la initfonction essaie déjà d'écrire dans la mémoire du tas.
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+        HEAP.lock().init(heap_start, HEAP_SIZE);
     }
+    HEAP_START.store(heap_start, Ordering::Relaxed);
+    HEAP_MAPPED.store(HEAP_SIZE, Ordering::Relaxed);
 
     Ok(())
 }
 
-//La première étape consiste à définir une région de mémoire virtuelle pour le tas. Nous pouvons choisir n'importe quelle plage d'adresses virtuelles que nous aimons, tant qu'elle n'est pas déjà utilisée pour une région de mémoire différente. Définissons-le comme la mémoire commençant à l'adresse 0x_4444_4444_0000afin que nous puissions facilement reconnaître un pointeur de tas plus tard
-pub const HEAP_START: usize = 0x_4444_4444_0000;
 //Nous avons défini la taille du tas sur 100 Kio pour l'instant. Si nous avons besoin de plus d'espace à l'avenir, nous pouvons simplement l'augmenter.
 pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+//Quand l'allocateur est a court de memoire, le tas grandit par pas d'au moins HEAP_GROWTH_STEP, jusqu'a la limite HEAP_MAX_SIZE.
+pub const HEAP_MAX_SIZE: usize = 16 * 1024 * 1024; // 16 MiB
+pub const HEAP_GROWTH_STEP: usize = 64 * 1024; // 64 KiB
+//Le tas ne contient que des donnees : ses pages ne sont jamais executables (W^X).
+const HEAP_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// Start of the heap region, set by `init_heap`.
+static HEAP_START: AtomicUsize = AtomicUsize::new(0);
+/// Number of heap bytes currently mapped, starting at `HEAP_START`.
+static HEAP_MAPPED: AtomicUsize = AtomicUsize::new(0);
+/// Upper bound for `HEAP_MAPPED`, see `set_heap_limit`.
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(HEAP_MAX_SIZE);
+
+/// Sets the size the heap is allowed to grow to, capped at `HEAP_MAX_SIZE`.
+///
+/// Memory that is already mapped stays part of the heap, a lower limit only
+/// stops further growth.
+pub fn set_heap_limit(limit: usize) {
+    HEAP_LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Returns the size the heap is allowed to grow to.
+pub fn heap_limit() -> usize {
+    HEAP_LIMIT.load(Ordering::Relaxed).min(HEAP_MAX_SIZE)
+}
+
+/// Returns the start address of the heap, or 0 before `init_heap`.
+pub fn heap_start() -> usize {
+    HEAP_START.load(Ordering::Relaxed)
+}
+
+/// Returns the number of bytes currently mapped for the heap.
+pub fn heap_size() -> usize {
+    HEAP_MAPPED.load(Ordering::Relaxed)
+}
+
+/// Maps at least `min_size` more bytes after the current end of the heap.
+///
+/// Returns the number of bytes that were added, or `None` if the heap limit
+/// is reached or the pages could not be mapped (e.g. before
+/// `memory::init_kernel_memory` was called).
+///
+/// Takes the `KernelMemory` lock: the caller must not hold the heap lock.
+fn grow_heap(min_size: usize) -> Option<usize> {
+    let heap_size = HEAP_MAPPED.load(Ordering::Relaxed);
+    if heap_size == 0 {
+        return None; // init_heap was not called yet
+    }
+    let limit = heap_limit();
+    // pres de la limite, on prend ce qui reste plutot qu'un pas complet, s'il suffit
+    let remaining = limit.saturating_sub(heap_size) & !4095;
+    let size = align_up(min_size.max(HEAP_GROWTH_STEP), 4096).min(remaining);
+    if size == 0 || size < min_size {
+        return None;
+    }
+
+    let grow_start = VirtAddr::new((heap_start() + heap_size) as u64);
+    let flags = HEAP_FLAGS;
+    //les morceaux de 2 MiB alignes sont mappes avec des grandes pages ; les pages deja mappees par une tentative precedente sont
+    //laissees telles quelles
+    let mapped = memory::with_kernel_memory(|memory| {
+        memory::huge::map_range(
+            &mut memory.mapper,
+            grow_start,
+            size as u64,
+            flags,
+            &mut memory.frame_allocator,
+        )
+    })?;
+    // une erreur au milieu laisse des pages mappees mais inutilisees, elles seront reprises a la prochaine tentative
+    mapped.ok()?;
+
+    HEAP_MAPPED.store(heap_size + size, Ordering::Relaxed);
+    Some(size)
+}
 
 unsafe impl GlobalAlloc for Dummy {
     unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
@@ -95,24 +263,105 @@ unsafe impl GlobalAlloc for Dummy {
     }
 }
 
+impl HeapAllocator for Heap {
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        Heap::init(self, heap_start, heap_size);
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        Heap::extend(self, by);
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        match self.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => null_mut(),
+        }
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        Heap::deallocate(self, core::ptr::NonNull::new(ptr).unwrap(), layout);
+    }
+
+    //linked_list_allocator ne donne pas acces a sa liste de trous
+    fn largest_free_block(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Held while the heap grows, so that `grow_heap` and the `extend` that
+/// follows it are not interleaved with another growth.
+static GROWING: spin::Mutex<()> = spin::Mutex::new(());
+
+//Tous les allocateurs du module passent par cette implementation: en cas d'echec, on agrandit le tas puis on reessaie.
+//Le verrou de l'allocateur est relache pendant grow_heap, qui prend KERNEL_MEMORY.
+unsafe impl<A: HeapAllocator> GlobalAlloc for Locked<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        use x86_64::instructions::interrupts::without_interrupts;
+
+        loop {
+            let ptr = without_interrupts(|| self.lock().allocate(layout));
+            if !ptr.is_null() {
+                return ptr;
+            }
+            let grown = without_interrupts(|| {
+                let _growing = GROWING.lock();
+                // leave room for the alignment and for the allocator's own bookkeeping
+                let by = grow_heap(layout.size() + layout.align() + 64)?;
+                self.lock().extend(by);
+                Some(())
+            });
+            if grown.is_none() {
+                return null_mut();
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        x86_64::instructions::interrupts::without_interrupts(|| self.lock().deallocate(ptr, layout));
+    }
+}
+
 //pour les implementation d'allocator ex allocator/bump
 pub struct Locked<A> {
     inner: spin::Mutex<A>,
+    counters: HeapCounters,
 }
 
 impl<A> Locked<A> {
     pub const fn new(inner: A) -> Self {
         Locked {
             inner: spin::Mutex::new(inner),
+            counters: HeapCounters::new(),
         }
     }
 
-    pub fn lock(&self) -> spin::MutexGuard<A> {
+    pub fn lock(&self) -> spin::MutexGuard<'_, A> {
         self.inner.lock()
     }
 }
 
-fn align_up(addr: usize, align: usize) -> usize {
-    (addr + align - 1) & !(align - 1)
+impl<A: HeapAllocator> Locked<A> {
+    /// Returns the current usage statistics of the wrapped allocator.
+    pub fn stats(&self) -> HeapStats {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let allocator = self.lock();
+            let mut stats = self.counters.snapshot();
+            stats.heap_size = heap_size();
+            stats.largest_free_block = allocator.largest_free_block();
+            stats.size_classes = allocator.size_classes();
+            stats
+        })
+    }
+}
+
+/// Returns the usage statistics of the global allocator.
+pub fn heap_stats() -> HeapStats {
+    HEAP.stats()
+}
+
+/// Prints the usage statistics of the global allocator to the serial port.
+pub fn print_heap_stats() {
+    crate::serial_println!("{}", heap_stats());
 }
 
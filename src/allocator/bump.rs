@@ -1,6 +1,6 @@
 //cf voir allocateur de bosses/bump
-use super::{align_up, Locked};
-use alloc::alloc::{GlobalAlloc, Layout};
+use super::{align_up, HeapAllocator};
+use alloc::alloc::Layout;
 use core::ptr;
 //Le nextpointeur ne se déplace que dans une seule direction et ne distribue donc jamais deux fois la même région de mémoire. 
 //Lorsqu'il atteint la fin du tas, plus aucune mémoire ne peut être allouée, ce qui entraîne une erreur de mémoire insuffisante lors de la prochaine allocation.
@@ -24,44 +24,48 @@ impl BumpAllocator {
             allocations: 0,
         }
     }
+}
 
+//Comme expliqué dans le post précédent , tous les allocations de tas doivent implémenter le GlobalAlloctrait
+//c'est l'implementation generique de GlobalAlloc pour Locked<A> (dans allocator.rs) qui verrouille l'allocateur et agrandit le tas si besoin
+impl HeapAllocator for BumpAllocator {
     //Nous avons choisi de créer une initfonction séparée au lieu d'effectuer l'initialisation directement dans newafin de garder l'interface identique à l'allocateur fourni par le linked_list_allocatorcrate. 
     //De cette façon, les répartiteurs peuvent être commutés sans modifications de code supplémentaires.
-    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
         self.heap_start = heap_start;
         self.heap_end = heap_start + heap_size;
         self.next = heap_start;
     }
-}
 
-//Comme expliqué dans le post précédent , tous les allocations de tas doivent implémenter le GlobalAlloctrait
-unsafe impl GlobalAlloc for Locked<BumpAllocator> {
-    //La première étape pour les deux alloc et dealloc consiste à appeler la Mutex::lockméthode via le innerchamp pour obtenir une référence mutable au type d'allocateur enveloppé.
-    //L'instance reste verrouillée jusqu'à la fin de la méthode, de sorte qu'aucune course aux données ne puisse se produire dans des contextes multithreads (nous ajouterons bientôt le support des threads).
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut bump = self.lock(); // get a mutable reference
+    unsafe fn extend(&mut self, by: usize) {
+        self.heap_end += by;
+    }
 
-        let alloc_start = align_up(bump.next, layout.align());
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let alloc_start = align_up(self.next, layout.align());
         let alloc_end = match alloc_start.checked_add(layout.size()) {
             Some(end) => end,
             None => return ptr::null_mut(),
         };
 
-        if alloc_end > bump.heap_end {
+        if alloc_end > self.heap_end {
             ptr::null_mut() // out of memory
         } else {
-            bump.next = alloc_end;
-            bump.allocations += 1;
+            self.next = alloc_end;
+            self.allocations += 1;
             alloc_start as *mut u8
         }
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        let mut bump = self.lock(); // get a mutable reference
-
-        bump.allocations -= 1;
-        if bump.allocations == 0 {
-            bump.next = bump.heap_start;
+    unsafe fn deallocate(&mut self, _ptr: *mut u8, _layout: Layout) {
+        self.allocations -= 1;
+        if self.allocations == 0 {
+            self.next = self.heap_start;
         }
     }
+
+    //seule la fin du tas est reutilisable tant qu'il reste une allocation
+    fn largest_free_block(&self) -> Option<usize> {
+        Some(self.heap_end - self.next)
+    }
 }
\ No newline at end of file
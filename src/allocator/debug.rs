@@ -0,0 +1,179 @@
+//Mode de debogage du tas (feature heap_debug). Chaque allocation est entouree de zones de garde remplies d'un motif connu et
+//precedee d'un en-tete qui garde sa taille, son alignement et son etat. A la liberation, on verifie l'en-tete et les gardes, puis on
+//empoisonne la memoire : un debordement, une double liberation ou un layout different sont detectes tout de suite au lieu de finir
+//en page fault bien plus tard.
+
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::{mem, ptr};
+
+/// Size of the guard zones before and after each allocation.
+pub const RED_ZONE: usize = 16;
+/// Byte pattern of the guard zones.
+pub const GUARD_BYTE: u8 = 0xfd;
+/// Byte pattern written over freshly allocated memory.
+pub const UNINIT_BYTE: u8 = 0xcd;
+/// Byte pattern written over freed memory.
+pub const POISON_BYTE: u8 = 0xdd;
+
+/// Room left at the start of each block for the free-list node the wrapped
+/// allocator writes there, so that the header survives the deallocation.
+const NODE_ROOM: usize = 32;
+
+const MAGIC: u32 = 0xa110_c8ed;
+const ALLOCATED: u32 = 1;
+const FREED: u32 = 2;
+
+/// Header stored right before the front guard zone.
+#[repr(C)]
+struct Header {
+    magic: u32,
+    state: u32,
+    size: usize,
+    align: usize,
+}
+
+/// A `GlobalAlloc` wrapper adding red zones, poisoning and double-free
+/// detection around another allocator.
+pub struct DebugAllocator<A> {
+    inner: A,
+}
+
+impl<A> DebugAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        DebugAllocator { inner }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns the offset of the user pointer from the start of the block.
+    fn prefix(layout: Layout) -> usize {
+        super::align_up(NODE_ROOM + mem::size_of::<Header>() + RED_ZONE, layout.align())
+    }
+
+    /// Returns the layout of the whole block (header, guards and user data).
+    fn block_layout(layout: Layout) -> Layout {
+        let size = Self::prefix(layout) + layout.size() + RED_ZONE;
+        let align = layout.align().max(mem::align_of::<Header>());
+        Layout::from_size_align(size, align).expect("debug allocation too large")
+    }
+
+    unsafe fn header(ptr: *mut u8) -> *mut Header {
+        ptr.sub(RED_ZONE + mem::size_of::<Header>()) as *mut Header
+    }
+
+    /// Returns whether the user data at `ptr` is still filled with
+    /// `POISON_BYTE`, as left by `dealloc`.
+    unsafe fn is_poisoned(ptr: *mut u8, layout: Layout) -> bool {
+        layout.size() > 0 && (0..layout.size()).all(|offset| ptr.add(offset).read() == POISON_BYTE)
+    }
+
+    /// Checks the header and the guard zones of the allocation at `ptr`.
+    unsafe fn check(ptr: *mut u8, layout: Layout) {
+        let header = Self::header(ptr).read();
+        if header.magic != MAGIC && Self::is_poisoned(ptr, layout) {
+            // l'en-tete d'un bloc libere a pu etre reutilise par l'allocateur enveloppe, mais les donnees empoisonnees sont encore la
+            panic!(
+                "heap debug: double free of {:p} (size {}, align {})",
+                ptr, layout.size(), layout.align()
+            );
+        }
+        if header.magic != MAGIC {
+            panic!(
+                "heap debug: invalid free or corrupted header at {:p} ({:?})",
+                ptr, layout
+            );
+        }
+        if header.state == FREED {
+            panic!(
+                "heap debug: double free of {:p} (size {}, align {})",
+                ptr, header.size, header.align
+            );
+        }
+        if header.size != layout.size() || header.align != layout.align() {
+            panic!(
+                "heap debug: layout mismatch for {:p}: allocated with size {} align {}, freed with {:?}",
+                ptr, header.size, header.align, layout
+            );
+        }
+
+        let front = ptr.sub(RED_ZONE);
+        let rear = ptr.add(layout.size());
+        for (zone, name) in [(front, "underflow"), (rear, "overflow")] {
+            for offset in 0..RED_ZONE {
+                if zone.add(offset).read() != GUARD_BYTE {
+                    panic!(
+                        "heap debug: buffer {} detected on {:p} (size {}, align {})",
+                        name, ptr, layout.size(), layout.align()
+                    );
+                }
+            }
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let block = self.inner.alloc(Self::block_layout(layout));
+        if block.is_null() {
+            return block;
+        }
+        let ptr = block.add(Self::prefix(layout));
+
+        Self::header(ptr).write(Header {
+            magic: MAGIC,
+            state: ALLOCATED,
+            size: layout.size(),
+            align: layout.align(),
+        });
+        ptr::write_bytes(ptr.sub(RED_ZONE), GUARD_BYTE, RED_ZONE);
+        ptr::write_bytes(ptr, UNINIT_BYTE, layout.size());
+        ptr::write_bytes(ptr.add(layout.size()), GUARD_BYTE, RED_ZONE);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Self::check(ptr, layout);
+
+        (*Self::header(ptr)).state = FREED;
+        ptr::write_bytes(ptr, POISON_BYTE, layout.size());
+        let block = ptr.sub(Self::prefix(layout));
+        self.inner.dealloc(block, Self::block_layout(layout));
+    }
+}
+
+//Ces tests utilisent un petit tas sur la pile pour ne pas dependre de l'allocateur global.
+#[cfg(test)]
+#[repr(align(4096))]
+struct TestArena([u8; 4096]);
+
+#[cfg(test)]
+fn test_allocator(arena: &mut TestArena) -> DebugAllocator<super::Locked<super::linked_list::LinkedListAllocator>> {
+    use super::{linked_list::LinkedListAllocator, HeapAllocator, Locked};
+
+    let heap = Locked::new(LinkedListAllocator::new());
+    unsafe { heap.lock().init(arena.0.as_mut_ptr() as usize, arena.0.len()) };
+    DebugAllocator::new(heap)
+}
+
+#[test_case]
+fn test_guards_and_poison() {
+    let mut arena = TestArena([0; 4096]);
+    let allocator = test_allocator(&mut arena);
+    let layout = Layout::from_size_align(24, 8).unwrap();
+
+    unsafe {
+        let ptr = allocator.alloc(layout);
+        assert!(!ptr.is_null());
+        assert_eq!(ptr.sub(1).read(), GUARD_BYTE);
+        assert_eq!(ptr.add(layout.size()).read(), GUARD_BYTE);
+        assert_eq!(ptr.read(), UNINIT_BYTE);
+
+        ptr::write_bytes(ptr, 0, layout.size());
+        allocator.dealloc(ptr, layout);
+        for offset in 0..layout.size() {
+            assert_eq!(ptr.add(offset).read(), POISON_BYTE);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 ///cf allocateur de block de taille fixe
-use super::Locked;
-use alloc::alloc::{GlobalAlloc, Layout};
+use super::stats::{SizeClassStats, SIZE_CLASSES};
+use super::HeapAllocator;
+use alloc::alloc::Layout;
 use core::{
     mem,
     ptr::{self, NonNull},
@@ -10,7 +11,7 @@ use core::{
 ///Comme tailles de bloc, nous utilisons des puissances de 2 à partir de 8 jusqu'à 2048. Nous ne définissons aucune taille de bloc inférieure à 8 car 
 ///chaque bloc doit être capable de stocker un pointeur 64 bits vers le bloc suivant lorsqu'il est libéré. Pour les allocations supérieures à 2048 octets, 
 ///nous recourrons à un alternateur de liste chaînée.
-const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
 /// Choose an appropriate block size for the given layout.
 ///
@@ -30,6 +31,10 @@ struct ListNode {
 pub struct FixedSizeBlockAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
     fallback_allocator: linked_list_allocator::Heap,
+    /// Blocks of each size class currently handed out.
+    blocks_in_use: [usize; BLOCK_SIZES.len()],
+    /// Blocks of each size class waiting in `list_heads`.
+    blocks_free: [usize; BLOCK_SIZES.len()],
 }
 
 impl FixedSizeBlockAllocator {
@@ -39,17 +44,11 @@ impl FixedSizeBlockAllocator {
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            blocks_in_use: [0; BLOCK_SIZES.len()],
+            blocks_free: [0; BLOCK_SIZES.len()],
         }
     }
 
-    /// Initialize the allocator with the given heap bounds.
-    ///
-    ///initappelle uniquement la initfonction de fallback_allocatorsans effectuer d'initialisation supplémentaire du list_headstableau. Au lieu de cela, 
-    /// nous allons initialiser paresseusement les listes allocet les deallocappels.
-    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
-        self.fallback_allocator.init(heap_start, heap_size);
-    }
-
     /// Allocates using the fallback allocator.
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
         match self.fallback_allocator.allocate_first_fit(layout) {
@@ -59,14 +58,27 @@ impl FixedSizeBlockAllocator {
     }
 }
 
-unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut allocator = self.lock();
+impl HeapAllocator for FixedSizeBlockAllocator {
+    /// Initialize the allocator with the given heap bounds.
+    ///
+    ///initappelle uniquement la initfonction de fallback_allocatorsans effectuer d'initialisation supplémentaire du list_headstableau. Au lieu de cela, 
+    /// nous allons initialiser paresseusement les listes allocet les deallocappels.
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.init(heap_start, heap_size);
+    }
+
+    //la memoire ajoutee revient a l'allocateur de secours, les listes de blocs se rempliront a partir de lui
+    unsafe fn extend(&mut self, by: usize) {
+        self.fallback_allocator.extend(by);
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
         match list_index(&layout) {
             Some(index) => {
-                match allocator.list_heads[index].take() {
+                let ptr = match self.list_heads[index].take() {
                     Some(node) => {
-                        allocator.list_heads[index] = node.next.take();
+                        self.list_heads[index] = node.next.take();
+                        self.blocks_free[index] -= 1;
                         node as *mut ListNode as *mut u8
                     }
                     None => {
@@ -75,32 +87,52 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                         // only works if all block sizes are a power of 2
                         let block_align = block_size;
                         let layout = Layout::from_size_align(block_size, block_align).unwrap();
-                        allocator.fallback_alloc(layout)
+                        self.fallback_alloc(layout)
                     }
+                };
+                if !ptr.is_null() {
+                    self.blocks_in_use[index] += 1;
                 }
+                ptr
             }
-            None => allocator.fallback_alloc(layout),
+            None => self.fallback_alloc(layout),
         }
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let mut allocator = self.lock();
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
         match list_index(&layout) {
             Some(index) => {
                 let new_node = ListNode {
-                    next: allocator.list_heads[index].take(),
+                    next: self.list_heads[index].take(),
                 };
                 // verify that block has size and alignment required for storing node
                 assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
                 assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
                 let new_node_ptr = ptr as *mut ListNode;
                 new_node_ptr.write(new_node);
-                allocator.list_heads[index] = Some(&mut *new_node_ptr);
+                self.list_heads[index] = Some(&mut *new_node_ptr);
+                self.blocks_in_use[index] -= 1;
+                self.blocks_free[index] += 1;
             }
             None => {
                 let ptr = NonNull::new(ptr).unwrap();
-                allocator.fallback_allocator.deallocate(ptr, layout);
+                self.fallback_allocator.deallocate(ptr, layout);
             }
         }
     }
+
+    //les grandes allocations viennent de l'allocateur de secours, et linked_list_allocator ne donne pas acces a sa liste de trous
+    fn largest_free_block(&self) -> Option<usize> {
+        None
+    }
+
+    fn size_classes(&self) -> Option<[SizeClassStats; SIZE_CLASSES]> {
+        let mut size_classes = [SizeClassStats::default(); SIZE_CLASSES];
+        for (index, class) in size_classes.iter_mut().enumerate() {
+            class.block_size = BLOCK_SIZES[index];
+            class.in_use = self.blocks_in_use[index];
+            class.free = self.blocks_free[index];
+        }
+        Some(size_classes)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,55 @@
+//Le trait commun a tous les allocateurs du tas. Ce fichier, comme bump.rs, linked_list.rs, fixed_size_block.rs et stats.rs,
+//ne depend que de core et alloc : heap_tests/ les inclut tels quels pour les tester sur l'hote.
+
+use super::stats::{SizeClassStats, SIZE_CLASSES};
+use alloc::alloc::Layout;
+
+/// Interface shared by the heap allocators of this module.
+///
+/// Besides allocating, every allocator has to be able to take over more memory
+/// right after its current end, so that the heap can grow on demand.
+pub trait HeapAllocator {
+    /// Initialize the allocator with the given heap bounds.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the given heap bounds are valid and that
+    /// the heap is unused. This method must be called only once.
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize);
+
+    /// Extends the heap by `by` bytes after its current end.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the memory right after the heap is
+    /// mapped and unused.
+    unsafe fn extend(&mut self, by: usize);
+
+    /// Allocates memory for `layout`, returning a null pointer when out of memory.
+    fn allocate(&mut self, layout: Layout) -> *mut u8;
+
+    /// Frees memory previously returned by `allocate` with the same `layout`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that `ptr` comes from this allocator and is
+    /// not used anymore.
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout);
+
+    /// Returns the size of the largest block that can be allocated without
+    /// growing the heap, or `None` if the allocator cannot tell.
+    fn largest_free_block(&self) -> Option<usize>;
+
+    /// Returns the block counts of each size class, for allocators that have
+    /// size classes.
+    fn size_classes(&self) -> Option<[SizeClassStats; SIZE_CLASSES]> {
+        None
+    }
+}
+
+/// Align the given address `addr` upwards to alignment `align`.
+///
+/// Requires that `align` is a power of two.
+pub(super) const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
@@ -2,8 +2,8 @@
 //nous n'avons besoin que d'un pointeur vers la première région inutilisée (appelée head) pour garder une trace de toutes les régions inutilisées, 
 //indépendamment de leur nombre. La structure de données résultante est souvent appelée une liste libre .
 
-use super::{align_up, Locked};
-use alloc::alloc::{GlobalAlloc, Layout};
+use super::{align_up, HeapAllocator};
+use alloc::alloc::Layout;
 use core::{mem, ptr};
 
 struct ListNode {
@@ -27,43 +27,77 @@ impl ListNode {
 
 
 
+/// How `LinkedListAllocator` picks the free region used for an allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitStrategy {
+    /// Use the first region (in address order) that is large enough.
+    FirstFit,
+    /// Use the smallest region that is large enough, keeping large regions
+    /// available for large allocations.
+    BestFit,
+}
+
 pub struct LinkedListAllocator {
     head: ListNode,
+    heap_end: usize,
+    strategy: FitStrategy,
 }
 
 impl LinkedListAllocator {
     /// Creates an empty LinkedListAllocator.
     pub const fn new() -> Self {
+        Self::with_strategy(FitStrategy::FirstFit)
+    }
+
+    /// Creates an empty LinkedListAllocator using the given fit strategy.
+    pub const fn with_strategy(strategy: FitStrategy) -> Self {
         Self {
             head: ListNode::new(0),
+            heap_end: 0,
+            strategy,
         }
     }
 
-    /// Initialize the allocator with the given heap bounds.
-    ///
-    /// This function is unsafe because the caller must guarantee that the given
-    /// heap bounds are valid and that the heap is unused. This method must be
-    /// called only once.
-    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
-        self.add_free_region(heap_start, heap_size);
-    }
-
-    //La méthode prend une région mémoire représentée par une adresse et une taille comme argument et l'ajoute au début de la liste. Tout d'abord,
-    // il garantit que la région donnée a la taille et l'alignement nécessaires pour stocker un fichier ListNode. Ensuite, il crée le nœud et l'insère 
-    //dans la liste en procédant comme suit
+    //La méthode prend une région mémoire représentée par une adresse et une taille comme argument et l'insère dans la liste, qui reste
+    //triée par adresse. Tout d'abord, il garantit que la région donnée a la taille et l'alignement nécessaires pour stocker un fichier
+    //ListNode. Si la région touche la région libre précédente ou suivante, elles sont fusionnées au lieu d'ajouter un nouveau nœud, ce qui
+    //évite que le tas ne se fragmente en petites régions.
 
-    /// Adds the given memory region to the front of the list.
+    /// Adds the given memory region to the list, keeping it sorted by address
+    /// and merging it with adjacent free regions.
     unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
         // ensure that the freed region is capable of holding ListNode
         assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
         assert!(size >= mem::size_of::<ListNode>());
 
-        // create a new list node and append it at the start of the list
-        let mut node = ListNode::new(size);
-        node.next = self.head.next.take();
-        let node_ptr = addr as *mut ListNode;
-        node_ptr.write(node);
-        self.head.next = Some(&mut *node_ptr)
+        // find the last region starting before `addr` (or the dummy head)
+        let head_addr = self.head.start_addr();
+        let mut current = &mut self.head;
+        while current.next.as_ref().is_some_and(|next| next.start_addr() < addr) {
+            current = current.next.as_mut().unwrap();
+        }
+
+        // merge with the following region if it starts right after the freed one
+        let mut size = size;
+        let mut next = current.next.take();
+        if let Some(following) = &mut next {
+            if addr + size == following.start_addr() {
+                size += following.size;
+                next = following.next.take();
+            }
+        }
+
+        if current.start_addr() != head_addr && current.end_addr() == addr {
+            // the previous region ends where the freed one starts -> grow it
+            current.size += size;
+            current.next = next;
+        } else {
+            let mut node = ListNode::new(size);
+            node.next = next;
+            let node_ptr = addr as *mut ListNode;
+            node_ptr.write(node);
+            current.next = Some(&mut *node_ptr)
+        }
     }
 
 
@@ -74,11 +108,21 @@ impl LinkedListAllocator {
     fn find_region(&mut self, size: usize, align: usize)
         -> Option<(&'static mut ListNode, usize)>
     {
+        // with best fit, look for the smallest suitable region first and only
+        // accept that one during the removal walk below
+        let best_fit = match self.strategy {
+            FitStrategy::FirstFit => None,
+            FitStrategy::BestFit => Some(self.find_best_fit(size, align)?),
+        };
+
         // reference to current list node, updated for each iteration
         let mut current = &mut self.head;
         // look for a large enough memory region in linked list
         while let Some(ref mut region) = current.next {
-            if let Ok(alloc_start) = Self::alloc_from_region(&region, size, align) {
+            let is_candidate = best_fit.is_none_or(|addr| region.start_addr() == addr);
+            if let (true, Ok(alloc_start)) =
+                (is_candidate, Self::alloc_from_region(region, size, align))
+            {
                 // region suitable for allocation -> remove node from list
                 let next = region.next.take();
                 let ret = Some((current.next.take().unwrap(), alloc_start));
@@ -94,6 +138,22 @@ impl LinkedListAllocator {
         None
     }
 
+    /// Returns the start address of the smallest region that can hold an
+    /// allocation with the given size and alignment.
+    fn find_best_fit(&self, size: usize, align: usize) -> Option<usize> {
+        let mut best: Option<&ListNode> = None;
+        let mut current = self.head.next.as_deref();
+        while let Some(region) = current {
+            if Self::alloc_from_region(region, size, align).is_ok()
+                && best.is_none_or(|best| region.size < best.size)
+            {
+                best = Some(region);
+            }
+            current = region.next.as_deref();
+        }
+        best.map(|region| region.start_addr())
+    }
+
     /// Try to use the given region for an allocation with given size and
     /// alignment.
     ///
@@ -101,7 +161,13 @@ impl LinkedListAllocator {
     fn alloc_from_region(region: &ListNode, size: usize, align: usize)
         -> Result<usize, ()>
     {
-        let alloc_start = align_up(region.start_addr(), align);
+        let mut alloc_start = align_up(region.start_addr(), align);
+        let padding = alloc_start - region.start_addr();
+        if padding > 0 && padding < mem::size_of::<ListNode>() {
+            // the padding before the allocation must be able to go back to the
+            // free list as its own region
+            alloc_start = align_up(region.start_addr() + mem::size_of::<ListNode>(), align);
+        }
         let alloc_end = alloc_start.checked_add(size).ok_or(())?;
 
         if alloc_end > region.end_addr() {
@@ -138,17 +204,35 @@ impl LinkedListAllocator {
 
 
 
-unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+impl HeapAllocator for LinkedListAllocator {
+    /// Initialize the allocator with the given heap bounds.
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// heap bounds are valid and that the heap is unused. This method must be
+    /// called only once.
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.add_free_region(heap_start, heap_size);
+        self.heap_end = heap_start + heap_size;
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        self.add_free_region(self.heap_end, by);
+        self.heap_end += by;
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
         // perform layout adjustments
         let (size, align) = LinkedListAllocator::size_align(layout);
-        let mut allocator = self.lock();
 
-        if let Some((region, alloc_start)) = allocator.find_region(size, align) {
+        if let Some((region, alloc_start)) = self.find_region(size, align) {
             let alloc_end = alloc_start.checked_add(size).expect("overflow");
+            let region_start = region.start_addr();
             let excess_size = region.end_addr() - alloc_end;
             if excess_size > 0 {
-                allocator.add_free_region(alloc_end, excess_size);
+                unsafe { self.add_free_region(alloc_end, excess_size) };
+            }
+            if alloc_start > region_start {
+                unsafe { self.add_free_region(region_start, alloc_start - region_start) };
             }
             alloc_start as *mut u8
         } else {
@@ -156,10 +240,73 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
         }
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
         // perform layout adjustments
         let (size, _) = LinkedListAllocator::size_align(layout);
 
-        self.lock().add_free_region(ptr as usize, size)
+        self.add_free_region(ptr as usize, size)
+    }
+
+    fn largest_free_block(&self) -> Option<usize> {
+        let mut largest = 0;
+        let mut current = self.head.next.as_deref();
+        while let Some(region) = current {
+            largest = largest.max(region.size);
+            current = region.next.as_deref();
+        }
+        Some(largest)
+    }
+}
+//Les tests utilisent un petit tas sur la pile plutot que le tas global du noyau.
+#[cfg(test)]
+#[repr(align(4096))]
+struct TestArena([u8; 4096]);
+
+#[test_case]
+fn test_adjacent_regions_are_merged() {
+    let mut arena = TestArena([0; 4096]);
+    let mut allocator = LinkedListAllocator::new();
+    unsafe { allocator.init(arena.0.as_mut_ptr() as usize, arena.0.len()) };
+
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let a = allocator.allocate(layout);
+    let b = allocator.allocate(layout);
+    let c = allocator.allocate(layout);
+    unsafe {
+        allocator.deallocate(a, layout);
+        allocator.deallocate(c, layout);
+        allocator.deallocate(b, layout);
     }
-}
\ No newline at end of file
+
+    // only possible if all freed regions were merged back into one
+    let whole = Layout::from_size_align(arena.0.len(), 8).unwrap();
+    assert_eq!(allocator.allocate(whole), arena.0.as_mut_ptr());
+}
+
+#[test_case]
+fn test_best_fit_uses_smallest_region() {
+    let mut arena = TestArena([0; 4096]);
+    let small = Layout::from_size_align(64, 8).unwrap();
+    let large = Layout::from_size_align(256, 8).unwrap();
+
+    for strategy in [FitStrategy::FirstFit, FitStrategy::BestFit] {
+        let mut allocator = LinkedListAllocator::with_strategy(strategy);
+        unsafe { allocator.init(arena.0.as_mut_ptr() as usize, arena.0.len()) };
+
+        // free regions of 256 and 64 bytes, kept apart by live allocations
+        let a = allocator.allocate(large);
+        let _separator = allocator.allocate(small);
+        let b = allocator.allocate(small);
+        let _rest = allocator.allocate(Layout::from_size_align(4096 - 2 * 64 - 256, 8).unwrap());
+        unsafe {
+            allocator.deallocate(a, large);
+            allocator.deallocate(b, small);
+        }
+
+        let expected = match strategy {
+            FitStrategy::FirstFit => a,
+            FitStrategy::BestFit => b,
+        };
+        assert_eq!(allocator.allocate(small), expected);
+    }
+}
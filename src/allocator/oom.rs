@@ -0,0 +1,150 @@
+//Gestion du manque de memoire. Quand le tas ne peut plus grandir, on appelle d'abord les fonctions de recuperation enregistrees
+//(vider un cache, reduire un SlabCache...) puis on reessaie l'allocation. Si rien n'a ete libere, alloc_error_handler affiche un
+//rapport detaille et arrete le CPU. Le code qui sait gerer un echec peut utiliser les fonctions try_* au lieu de Box::new ou Vec.
+
+use super::stats::HeapStats;
+use super::{heap_limit, heap_stats};
+use alloc::alloc::{alloc, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+/// A reclaim callback, called with the number of bytes the failed allocation
+/// needs. Returns the number of bytes it freed.
+pub type ReclaimFn = fn(needed: usize) -> usize;
+
+/// Maximum number of reclaim callbacks.
+pub const MAX_RECLAIMERS: usize = 8;
+
+static RECLAIMERS: Mutex<[Option<ReclaimFn>; MAX_RECLAIMERS]> = Mutex::new([None; MAX_RECLAIMERS]);
+/// Set while the callbacks run, so that an allocation failing inside a
+/// callback does not start another round.
+static RECLAIMING: AtomicBool = AtomicBool::new(false);
+
+/// Registers a callback that is invoked when the heap is out of memory.
+///
+/// Gives the callback back if `MAX_RECLAIMERS` callbacks are registered already.
+pub fn register_reclaimer(reclaimer: ReclaimFn) -> Result<(), ReclaimFn> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut reclaimers = RECLAIMERS.lock();
+        match reclaimers.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(reclaimer);
+                Ok(())
+            }
+            None => Err(reclaimer),
+        }
+    })
+}
+
+/// Calls every registered callback and returns the number of bytes they freed.
+///
+/// Returns 0 without calling anything when called from within a callback.
+pub fn reclaim(needed: usize) -> usize {
+    if RECLAIMING.swap(true, Ordering::Acquire) {
+        return 0;
+    }
+    // les callbacks liberent de la memoire, on ne garde donc pas le verrou pendant leur execution
+    let reclaimers = x86_64::instructions::interrupts::without_interrupts(|| *RECLAIMERS.lock());
+    let mut freed = 0;
+    for reclaimer in reclaimers.iter().flatten() {
+        freed += reclaimer(needed);
+    }
+    RECLAIMING.store(false, Ordering::Release);
+    freed
+}
+
+/// Prints what is known about a failed allocation to the serial port and the
+/// screen.
+pub fn report(layout: Layout) {
+    let report = OomReport::new(layout);
+    crate::serial_println!("{}", report);
+    crate::println!("{}", report);
+}
+
+/// The state of the heap when an allocation failed, as printed by `report`.
+#[derive(Debug, Clone, Copy)]
+pub struct OomReport {
+    pub layout: Layout,
+    pub heap_limit: usize,
+    pub stats: HeapStats,
+}
+
+impl OomReport {
+    /// Takes a snapshot of the heap for the failed allocation `layout`.
+    pub fn new(layout: Layout) -> Self {
+        OomReport {
+            layout,
+            heap_limit: heap_limit(),
+            stats: heap_stats(),
+        }
+    }
+}
+
+impl fmt::Display for OomReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "OUT OF MEMORY: failed to allocate {} bytes (align {})",
+            self.layout.size(),
+            self.layout.align()
+        )?;
+        writeln!(f, "heap limit: {} bytes", self.heap_limit)?;
+        if self.stats.largest_free_block.is_none() {
+            writeln!(f, "largest free block: unknown")?;
+        }
+        write!(f, "{}", self.stats)
+    }
+}
+
+/// Error returned by the `try_*` helpers when the memory could not be allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError {
+    /// Number of bytes that were requested.
+    pub size: usize,
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to allocate {} bytes", self.size)
+    }
+}
+
+/// Like `Box::new`, but gives the value back instead of calling
+/// `alloc_error_handler` when the allocation fails.
+pub fn try_box<T>(value: T) -> Result<Box<T>, T> {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        return Ok(Box::new(value));
+    }
+    let ptr = unsafe { alloc(layout) } as *mut T;
+    if ptr.is_null() {
+        return Err(value);
+    }
+    unsafe {
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
+    }
+}
+
+/// Like `Vec::with_capacity`, but returns an error instead of calling
+/// `alloc_error_handler` when the allocation fails.
+pub fn try_vec_with_capacity<T>(capacity: usize) -> Result<Vec<T>, AllocError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(capacity).map_err(|_| AllocError {
+        size: capacity.saturating_mul(mem::size_of::<T>()),
+    })?;
+    Ok(vec)
+}
+
+/// Appends `value` to `vec`, giving it back if the vector could not grow.
+pub fn try_push<T>(vec: &mut Vec<T>, value: T) -> Result<(), T> {
+    if vec.try_reserve(1).is_err() {
+        return Err(value);
+    }
+    vec.push(value);
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//Un cache slab decoupe des pages entieres (les slabs) en objets d'une seule taille. Chaque slab garde sa propre liste d'objets libres,
+//donc allouer ou liberer un objet est en O(1) et il n'y a pas de fragmentation entre objets de tailles differentes. Les slabs sont
+//ranges en trois listes (partiels, pleins, vides) et les slabs vides sont rendus a l'allocateur global.
+
+use super::align_up;
+use alloc::alloc::{alloc, dealloc, Layout};
+use core::{
+    marker::PhantomData,
+    mem,
+    ptr::{self, NonNull},
+};
+
+/// Size (and alignment) of a slab.
+pub const SLAB_SIZE: usize = 4096;
+
+/// Number of empty slabs kept around before they are released to the heap.
+const MAX_EMPTY_SLABS: usize = 1;
+
+/// Header stored at the start of every slab.
+struct Slab {
+    next: *mut Slab,
+    prev: *mut Slab,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+/// Node of a slab's free object list, written into the free objects.
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// A doubly linked list of slabs.
+struct SlabList {
+    head: *mut Slab,
+    len: usize,
+}
+
+impl SlabList {
+    const fn new() -> Self {
+        SlabList {
+            head: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.head;
+        if !self.head.is_null() {
+            (*self.head).prev = slab;
+        }
+        self.head = slab;
+        self.len += 1;
+    }
+
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.head = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+        self.len -= 1;
+    }
+}
+
+/// An object cache handing out `T`s carved from page-sized slabs.
+pub struct SlabCache<T> {
+    partial: SlabList,
+    full: SlabList,
+    empty: SlabList,
+    allocated: usize,
+    _marker: PhantomData<T>,
+}
+
+//les pointeurs bruts vers les slabs ne sont accessibles qu'a travers &mut SlabCache
+unsafe impl<T: Send> Send for SlabCache<T> {}
+
+impl<T> SlabCache<T> {
+    /// Alignment of the objects in a slab.
+    const OBJECT_ALIGN: usize = max(mem::align_of::<T>(), mem::align_of::<FreeObject>());
+    /// Size of the objects in a slab, large enough to hold a `FreeObject`.
+    const OBJECT_SIZE: usize = align_up(
+        max(mem::size_of::<T>(), mem::size_of::<FreeObject>()),
+        Self::OBJECT_ALIGN,
+    );
+    /// Offset of the first object, right after the slab header.
+    const FIRST_OBJECT: usize = align_up(mem::size_of::<Slab>(), Self::OBJECT_ALIGN);
+    /// Evaluated by `new`: a `T` that is more aligned than a slab, or that does
+    /// not fit after the header, is rejected at compile time.
+    const FITS_IN_SLAB: () = assert!(
+        Self::OBJECT_ALIGN <= SLAB_SIZE && Self::FIRST_OBJECT + Self::OBJECT_SIZE <= SLAB_SIZE,
+        "object too large for a slab cache"
+    );
+
+    /// Creates an empty cache. No memory is allocated until the first `alloc`.
+    ///
+    /// `T` must fit in a slab next to its header, with an alignment of at most
+    /// `SLAB_SIZE`; otherwise the instantiation fails to compile.
+    #[allow(clippy::let_unit_value)]
+    pub const fn new() -> Self {
+        let () = Self::FITS_IN_SLAB;
+        SlabCache {
+            partial: SlabList::new(),
+            full: SlabList::new(),
+            empty: SlabList::new(),
+            allocated: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the number of objects that fit in one slab.
+    pub const fn objects_per_slab() -> usize {
+        (SLAB_SIZE - Self::FIRST_OBJECT) / Self::OBJECT_SIZE
+    }
+
+    /// Moves `value` into a free object of the cache.
+    ///
+    /// Gives the value back if no slab could be allocated.
+    pub fn alloc(&mut self, value: T) -> Result<NonNull<T>, T> {
+        unsafe {
+            let slab = if !self.partial.head.is_null() {
+                self.partial.head
+            } else if !self.empty.head.is_null() {
+                let slab = self.empty.head;
+                self.empty.remove(slab);
+                self.partial.push(slab);
+                slab
+            } else {
+                match Self::new_slab() {
+                    Some(slab) => {
+                        self.partial.push(slab);
+                        slab
+                    }
+                    None => return Err(value),
+                }
+            };
+
+            let object = (*slab).free;
+            (*slab).free = (*object).next;
+            (*slab).in_use += 1;
+            if (*slab).free.is_null() {
+                self.partial.remove(slab);
+                self.full.push(slab);
+            }
+            self.allocated += 1;
+
+            let object = object as *mut T;
+            object.write(value);
+            Ok(NonNull::new_unchecked(object))
+        }
+    }
+
+    /// Drops the object at `ptr` and returns its memory to the cache.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that `ptr` was returned by `alloc` on this
+    /// cache and was not freed already.
+    pub unsafe fn free(&mut self, ptr: NonNull<T>) {
+        let object = ptr.as_ptr();
+        ptr::drop_in_place(object);
+
+        // les slabs sont alignes sur leur taille, l'en-tete se trouve donc au debut de la page
+        let slab = (object as usize & !(SLAB_SIZE - 1)) as *mut Slab;
+        let was_full = (*slab).free.is_null();
+        let object = object as *mut FreeObject;
+        object.write(FreeObject { next: (*slab).free });
+        (*slab).free = object;
+        (*slab).in_use -= 1;
+        self.allocated -= 1;
+
+        if was_full {
+            self.full.remove(slab);
+            self.partial.push(slab);
+        }
+        if (*slab).in_use == 0 {
+            self.partial.remove(slab);
+            if self.empty.len < MAX_EMPTY_SLABS {
+                self.empty.push(slab);
+            } else {
+                Self::release_slab(slab);
+            }
+        }
+    }
+
+    /// Releases all empty slabs to the heap.
+    ///
+    /// Returns the number of bytes given back.
+    pub fn shrink(&mut self) -> usize {
+        let mut released = 0;
+        while !self.empty.head.is_null() {
+            unsafe {
+                let slab = self.empty.head;
+                self.empty.remove(slab);
+                Self::release_slab(slab);
+            }
+            released += SLAB_SIZE;
+        }
+        released
+    }
+
+    /// Returns the number of live objects.
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    /// Returns the number of partially full, full and empty slabs.
+    pub fn slab_counts(&self) -> (usize, usize, usize) {
+        (self.partial.len, self.full.len, self.empty.len)
+    }
+
+    fn slab_layout() -> Layout {
+        Layout::from_size_align(SLAB_SIZE, SLAB_SIZE).unwrap()
+    }
+
+    /// Allocates a slab from the heap and threads all its objects on its free list.
+    unsafe fn new_slab() -> Option<*mut Slab> {
+        let slab = alloc(Self::slab_layout()) as *mut Slab;
+        if slab.is_null() {
+            return None;
+        }
+
+        let mut free = ptr::null_mut();
+        for index in (0..Self::objects_per_slab()).rev() {
+            let object =
+                (slab as usize + Self::FIRST_OBJECT + index * Self::OBJECT_SIZE) as *mut FreeObject;
+            object.write(FreeObject { next: free });
+            free = object;
+        }
+        slab.write(Slab {
+            next: ptr::null_mut(),
+            prev: ptr::null_mut(),
+            free,
+            in_use: 0,
+        });
+        Some(slab)
+    }
+
+    unsafe fn release_slab(slab: *mut Slab) {
+        dealloc(slab as *mut u8, Self::slab_layout());
+    }
+}
+
+impl<T> Default for SlabCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SlabCache<T> {
+    /// Releases every slab of the cache. Objects that are still allocated are
+    /// not dropped.
+    fn drop(&mut self) {
+        self.shrink();
+        for list in [&mut self.partial, &mut self.full] {
+            while !list.head.is_null() {
+                unsafe {
+                    let slab = list.head;
+                    list.remove(slab);
+                    Self::release_slab(slab);
+                }
+            }
+        }
+    }
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
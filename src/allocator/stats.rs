@@ -0,0 +1,130 @@
+//Statistiques d'utilisation du tas, communes a tous les allocateurs. Les compteurs (octets utilises, pic, nombre d'allocations) sont
+//gardes dans Locked et mis a jour par l'allocateur global avec les tailles demandees par l'appelant. Les allocateurs ne fournissent que
+//ce qu'eux seuls connaissent : le plus grand bloc libre et les classes de taille.
+
+use super::fixed_size_block::BLOCK_SIZES;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of size classes of `FixedSizeBlockAllocator`.
+pub const SIZE_CLASSES: usize = BLOCK_SIZES.len();
+
+/// A snapshot of the heap usage.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes currently mapped for the heap.
+    pub heap_size: usize,
+    /// Bytes requested by live allocations.
+    pub bytes_in_use: usize,
+    /// Highest value `bytes_in_use` ever reached.
+    pub peak_bytes_in_use: usize,
+    /// Number of successful allocations since boot.
+    pub allocations: usize,
+    /// Number of deallocations since boot.
+    pub deallocations: usize,
+    /// Largest allocation that can be served without growing the heap, if the
+    /// allocator can tell.
+    pub largest_free_block: Option<usize>,
+    /// Block counts per size class, for allocators that have size classes.
+    pub size_classes: Option<[SizeClassStats; SIZE_CLASSES]>,
+}
+
+impl HeapStats {
+    /// Returns the number of live allocations.
+    pub fn live_allocations(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+}
+
+/// Block counts of one size class of `FixedSizeBlockAllocator`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeClassStats {
+    pub block_size: usize,
+    /// Blocks currently handed out.
+    pub in_use: usize,
+    /// Blocks waiting in the free list.
+    pub free: usize,
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "heap: {} bytes mapped, {} in use (peak {})",
+            self.heap_size, self.bytes_in_use, self.peak_bytes_in_use
+        )?;
+        write!(
+            f,
+            "  {} allocations, {} deallocations, {} live",
+            self.allocations,
+            self.deallocations,
+            self.live_allocations()
+        )?;
+        if let Some(largest) = self.largest_free_block {
+            write!(f, "\n  largest free block: {} bytes", largest)?;
+        }
+        if let Some(size_classes) = &self.size_classes {
+            for class in size_classes {
+                write!(
+                    f,
+                    "\n  {:>4} bytes: {} in use, {} free",
+                    class.block_size, class.in_use, class.free
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Usage counters updated on every allocation and deallocation.
+///
+/// They are updated by the global allocator with the sizes requested by its
+/// callers; the atomics make it possible to read them without taking the lock.
+pub struct HeapCounters {
+    bytes_in_use: AtomicUsize,
+    peak_bytes_in_use: AtomicUsize,
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+}
+
+impl Default for HeapCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeapCounters {
+    pub const fn new() -> Self {
+        HeapCounters {
+            bytes_in_use: AtomicUsize::new(0),
+            peak_bytes_in_use: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            deallocations: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn record_alloc(&self, size: usize) {
+        let in_use = self.bytes_in_use.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes_in_use.fetch_max(in_use, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dealloc(&self, size: usize) {
+        self.bytes_in_use.fetch_sub(size, Ordering::Relaxed);
+        self.deallocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns stats holding the counter values, the allocator specific
+    /// fields are left empty.
+    pub fn snapshot(&self) -> HeapStats {
+        HeapStats {
+            heap_size: 0,
+            bytes_in_use: self.bytes_in_use.load(Ordering::Relaxed),
+            peak_bytes_in_use: self.peak_bytes_in_use.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+            deallocations: self.deallocations.load(Ordering::Relaxed),
+            largest_free_block: None,
+            size_classes: None,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//Protections du processeur contre les acces du noyau a la memoire utilisateur. SMEP interdit au noyau d'executer une page
+//utilisateur, SMAP de la lire ou de l'ecrire (sauf entre STAC et CLAC), et UMIP interdit au code utilisateur les instructions qui
+//devoilent des adresses du noyau (SGDT, SIDT, SLDT, SMSW, STR). Chacune n'est activee que si CPUID la signale.
+
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+/// The protection features supported (or enabled) on this CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Protections {
+    /// Supervisor Mode Execution Prevention.
+    pub smep: bool,
+    /// Supervisor Mode Access Prevention.
+    pub smap: bool,
+    /// User Mode Instruction Prevention.
+    pub umip: bool,
+}
+
+static SMEP_ENABLED: AtomicBool = AtomicBool::new(false);
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+static UMIP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Returns the protections reported by CPUID leaf 7.
+pub fn supported_protections() -> Protections {
+    if __cpuid(0).eax < 7 {
+        return Protections::default();
+    }
+    let leaf = __cpuid_count(7, 0);
+    Protections {
+        smep: leaf.ebx & (1 << 7) != 0,
+        smap: leaf.ebx & (1 << 20) != 0,
+        umip: leaf.ecx & (1 << 2) != 0,
+    }
+}
+
+/// Enables every protection supported by the CPU in CR4 and returns them.
+///
+/// Called by `blog_os::init`.
+pub fn enable_protections() -> Protections {
+    let supported = supported_protections();
+    let mut flags = Cr4Flags::empty();
+    flags.set(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION, supported.smep);
+    flags.set(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION, supported.smap);
+    flags.set(Cr4Flags::USER_MODE_INSTRUCTION_PREVENTION, supported.umip);
+    unsafe { Cr4::update(|cr4| *cr4 |= flags) };
+
+    SMEP_ENABLED.store(supported.smep, Ordering::Relaxed);
+    SMAP_ENABLED.store(supported.smap, Ordering::Relaxed);
+    UMIP_ENABLED.store(supported.umip, Ordering::Relaxed);
+    supported
+}
+
+/// Returns the protections enabled by `enable_protections`.
+pub fn enabled_protections() -> Protections {
+    Protections {
+        smep: SMEP_ENABLED.load(Ordering::Relaxed),
+        smap: SMAP_ENABLED.load(Ordering::Relaxed),
+        umip: UMIP_ENABLED.load(Ordering::Relaxed),
+    }
+}
+
+/// Runs `f` with user pages accessible to the kernel despite SMAP.
+///
+/// The access flag (RFLAGS.AC) is set with STAC before `f` and cleared
+/// with CLAC by a guard dropped after it; without SMAP, `f` is simply
+/// called. Only code that checked the user addresses it touches should run
+/// inside `f`.
+///
+/// Interrupts are disabled meanwhile, so that no interrupt handler runs
+/// with user pages accessible.
+pub fn with_user_access<R>(f: impl FnOnce() -> R) -> R {
+    // STAC et CLAC n'existent pas sans SMAP (#UD)
+    if !SMAP_ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        // pas de nomem : les acces de f ne doivent pas etre deplaces hors de la fenetre STAC/CLAC
+        unsafe { core::arch::asm!("stac", options(nostack)) };
+        let _access = UserAccess;
+        f()
+    })
+}
+
+/// Clears RFLAGS.AC when dropped, closing the window opened by
+/// `with_user_access`.
+struct UserAccess;
+
+impl Drop for UserAccess {
+    fn drop(&mut self) {
+        end_user_access();
+    }
+}
+
+/// Makes user pages inaccessible to the kernel again if SMAP is enabled.
+///
+/// The kernel aborts on panic, so the guard of `with_user_access` does not
+/// run when `f` panics: the crash path calls this instead.
+pub fn end_user_access() {
+    if SMAP_ENABLED.load(Ordering::Relaxed) {
+        unsafe { core::arch::asm!("clac", options(nostack)) };
+    }
+}
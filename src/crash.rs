@@ -0,0 +1,73 @@
+//Chemin commun des erreurs fatales : exception non resolue ou panique du noyau. Le rapport complet (message, registres generaux,
+//registres de controle et EFER decodes) est ecrit sur le port serie puis affiche en page de plantage sur l'ecran VGA, et le
+//processeur est arrete, interruptions desactivees. Les verrous de l'ecran et du port serie sont forces : la faute a pu arriver
+//pendant un affichage.
+
+use crate::interrupts::exceptions::{ExceptionReport, RegisterSnapshot};
+use crate::{hlt_loop, serial, serial_println, vga_buffer};
+use core::fmt;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by the first crash; a fault while reporting it just halts.
+static CRASHING: AtomicBool = AtomicBool::new(false);
+
+/// Called by `crash` before halting, e.g. by tests checking the report.
+static CRASH_HOOK: spin::Mutex<Option<fn(&ExceptionReport)>> = spin::Mutex::new(None);
+
+/// Called by `panic` before halting, e.g. by tests checking the crash page.
+static PANIC_HOOK: spin::Mutex<Option<fn(&PanicInfo)>> = spin::Mutex::new(None);
+
+/// Sets the function `crash` calls with the report before halting.
+pub fn set_crash_hook(hook: fn(&ExceptionReport)) {
+    *CRASH_HOOK.lock() = Some(hook);
+}
+
+/// Sets the function `panic` calls with the panic information before
+/// halting.
+pub fn set_panic_hook(hook: fn(&PanicInfo)) {
+    *PANIC_HOOK.lock() = Some(hook);
+}
+
+/// Writes `body` to the serial port and on a crash page titled `title`.
+fn report(title: &str, body: fmt::Arguments) {
+    unsafe { serial::SERIAL1.force_unlock() };
+    serial_println!("{}\n{}", title, body);
+    vga_buffer::crash_screen(title, body);
+}
+
+/// Stops everything else and disables interrupts. Returns `false` if a crash
+/// is being reported already.
+fn begin() -> bool {
+    x86_64::instructions::interrupts::disable();
+    // la faute a pu arriver dans cpu::with_user_access : SMAP doit rester actif pendant le rapport
+    crate::cpu::end_user_access();
+    !CRASHING.swap(true, Ordering::SeqCst)
+}
+
+/// Reports an exception that could not be handled, then halts the CPU for
+/// good.
+pub fn crash(exception: &ExceptionReport) -> ! {
+    if begin() {
+        report("KERNEL CRASH", format_args!("{}", exception));
+        let hook = CRASH_HOOK.try_lock().and_then(|hook| *hook);
+        if let Some(hook) = hook {
+            hook(exception);
+        }
+    }
+    hlt_loop();
+}
+
+/// Reports a kernel panic with the registers of the panicking code, then
+/// halts the CPU for good.
+pub fn panic(info: &PanicInfo) -> ! {
+    let registers = RegisterSnapshot::current();
+    if begin() {
+        report("KERNEL PANIC", format_args!("{}\n\n{}", info, registers));
+        let hook = PANIC_HOOK.try_lock().and_then(|hook| *hook);
+        if let Some(hook) = hook {
+            hook(info);
+        }
+    }
+    hlt_loop();
+}
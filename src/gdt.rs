@@ -3,28 +3,33 @@ use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
+//Seule la double fault a sa propre pile. La page fault reste sur la pile courante : une page fault imbriquee reprendrait le haut
+//d'une pile IST dediee et ecraserait la trame de la premiere. Quand une pile deborde dans sa page de garde, le CPU ne peut pas
+//empiler la trame de la page fault et leve une double fault, qui tourne sur cette pile IST.
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
-    };
+//Le TSS est modifiable pour que les piles statiques du demarrage puissent etre remplacees par des piles avec page de garde une
+//fois la memoire initialisee (voir memory::stack::init_interrupt_stacks). Le CPU relit l'IST a chaque interruption.
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+/// Returns the top of a 20 KiB static stack, used until `set_interrupt_stack`
+/// installs a guarded one.
+macro_rules! static_stack {
+    () => {{
+        const STACK_SIZE: usize = 4096 * 5;
+        static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+        let stack_start = VirtAddr::from_ptr(core::ptr::addr_of!(STACK));
+        let stack_end = stack_start + STACK_SIZE;
+        stack_end
+    }};
 }
 
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(unsafe { &*core::ptr::addr_of!(TSS) }));
         (
             gdt,
             Selectors {
@@ -44,9 +49,25 @@ pub fn init() {
     use x86_64::instructions::segmentation::{Segment, CS};
     use x86_64::instructions::tables::load_tss;
 
+    unsafe {
+        TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = static_stack!();
+    }
     GDT.0.load();
     unsafe {
         CS::set_reg(GDT.1.code_selector);
         load_tss(GDT.1.tss_selector);
     }
-}
\ No newline at end of file
+}
+/// Makes the interrupt stack table entry `index` point to the stack ending at
+/// `stack_top`.
+///
+/// # Safety
+///
+/// The caller must guarantee that the stack is mapped and stays valid for as
+/// long as the entry uses it, and that no interrupt is currently running on
+/// the old stack of the entry.
+pub unsafe fn set_interrupt_stack(index: u16, stack_top: VirtAddr) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        TSS.interrupt_stack_table[index as usize] = stack_top;
+    });
+}
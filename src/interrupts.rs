@@ -1,4 +1,5 @@
-use crate::{gdt,  hlt_loop,print, println};
+use crate::{print, println};
+use core::sync::atomic::{AtomicU64, Ordering};
 use lazy_static::lazy_static;
 //interruption materiel
 use pic8259::ChainedPics;
@@ -6,6 +7,13 @@ use spin;
 //fautes simple et double
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 
+//local APIC et I/O APIC, qui remplacent les PIC quand la MADT les decrit
+pub mod apic;
+//enregistrement des gestionnaires d'interruptions materielles
+pub mod irq;
+//rapport et arret du noyau sur les exceptions du processeur
+pub mod exceptions;
+
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
@@ -22,30 +30,76 @@ impl InterruptIndex {
         self as u8
     }
 
-    fn as_usize(self) -> usize {
-        usize::from(self.as_u8())
+    /// Returns the interrupt line of the interrupt.
+    fn irq(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
     }
 }
 
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
+/// The controller delivering the hardware interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptController {
+    /// The two legacy 8259 PICs, set up by `blog_os::init`.
+    Pic,
+    /// The local APIC and the I/O APICs described by the ACPI MADT.
+    Apic,
+}
+
+/// Switches to the requested interrupt controller and returns the one in use.
+///
+/// Asking for `Apic` on a machine without APIC (or before
+/// `memory::init_kernel_memory`) keeps the PICs. The lines registered with
+/// `irq::register` keep their handlers and vectors whatever the controller.
+pub fn select_controller(controller: InterruptController) -> InterruptController {
+    if controller == InterruptController::Pic || apic::is_enabled() {
+        return active_controller();
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let result = apic::init().map_err(irq::IrqError::from).and_then(|()| irq::reroute());
+        if let Err(err) = result {
+            //si init a reussi mais pas le routage, les PIC reprennent la main
+            apic::disable();
+            irq::reroute().expect("failed to unmask the 8259 PIC lines");
+            println!("APIC unavailable ({:?}), using the 8259 PICs", err);
+        }
+    });
+    active_controller()
+}
+
+/// Returns the controller currently delivering the hardware interrupts.
+pub fn active_controller() -> InterruptController {
+    if apic::is_enabled() {
+        InterruptController::Apic
+    } else {
+        InterruptController::Pic
+    }
+}
+
+/// Registers the timer and keyboard handlers and masks the other lines.
+pub fn init_irqs() {
+    irq::init();
+    irq::register(InterruptIndex::Timer.irq(), irq::Sharing::Shared, timer_interrupt_handler)
+        .expect("failed to register the timer handler");
+    irq::register(InterruptIndex::Keyboard.irq(), irq::Sharing::Exclusive, keyboard_interrupt_handler)
+        .expect("failed to register the keyboard handler");
+}
+
 //faute simple et double
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
         idt.breakpoint.set_handler_fn(breakpoint_handler);
-        //pagination
-        idt.page_fault.set_handler_fn(page_fault_handler);
-        //gestionnaire de double faute
-        unsafe {
-            idt.double_fault
-                .set_handler_fn(double_fault_handler)
-                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        //toutes les autres exceptions passent par les stubs d'exceptions, qui sauvegardent les registres (la double
+        //faute sur sa propre pile)
+        exceptions::install(&mut idt);
+        //chaque ligne d'interruption materielle passe par irq, qui appelle les gestionnaires enregistres
+        for (line, &stub) in irq::STUBS.iter().enumerate() {
+            idt[usize::from(irq::vector(line as u8))].set_handler_fn(stub);
         }
-        //ajout d'une fonction de gestionnaire pour l'interruption du minuteur
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        idt[usize::from(apic::SPURIOUS_VECTOR)].set_handler_fn(spurious_interrupt_handler);
         idt
     };
 }
@@ -58,49 +112,42 @@ extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 //pagination
-extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
+/// Tries to resolve a page fault, called by the page fault stub. The
+/// faulting instruction is executed again if it returns `true`.
+fn resolve_page_fault(error_code: PageFaultErrorCode) -> bool {
     use x86_64::registers::control::Cr2;
 
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error Code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
-    hlt_loop();
+    //une faute dans une region paresseuse est resolue en mappant la page, l'instruction est alors reexecutee
+    if crate::memory::vmm::resolve_page_fault(Cr2::read(), error_code) {
+        return true;
+    }
+    //une ecriture dans une page partagee en copie a l'ecriture lui donne sa propre frame
+    crate::memory::cow::resolve_page_fault(Cr2::read(), error_code)
 }
 
-// gestionnaire de double faute
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame, _error_code: u64) -> !
-{
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+static TIMER_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of timer interrupts received since boot.
+pub fn timer_ticks() -> u64 {
+    TIMER_TICKS.load(Ordering::Relaxed)
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+fn timer_interrupt_handler() {
+    TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
     print!(".");
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    }
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(
-    _stack_frame: InterruptStackFrame
-) {
+fn keyboard_interrupt_handler() {
     use x86_64::instructions::port::Port;
 
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
     crate::task::keyboard::add_scancode(scancode); // new
-
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
-    }
 }
 
+//une interruption parasite du local APIC ne doit pas etre acquittee
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
 #[test_case]
 fn test_breakpoint_exception() {
     // invoke a breakpoint exception
@@ -0,0 +1,292 @@
+//Local APIC et I/O APIC. Le local APIC de chaque processeur recoit les interruptions et prend leur fin (EOI) ; l'I/O APIC remplace
+//les 8259 : chacune de ses entrees de redirection associe une interruption materielle (GSI) a un vecteur et a un processeur.
+//Les deux sont des registres memoire (MMIO) dont l'adresse physique vient de la MADT ; ils sont mappes sans cache dans des regions
+//Mmio du gestionnaire d'adresses virtuelles.
+
+use crate::acpi::{self, Madt, MAX_IO_APICS};
+use crate::memory::{
+    self,
+    vmm::{self, RegionKind, VmError},
+};
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::{instructions::port::Port, structures::paging::PageTableFlags, PhysAddr, VirtAddr};
+
+/// Vector of the spurious interrupts sent by the local APIC.
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+// registres du local APIC (decalage depuis sa base)
+const LAPIC_ID: u64 = 0x20;
+const LAPIC_TASK_PRIORITY: u64 = 0x80;
+const LAPIC_EOI: u64 = 0xb0;
+const LAPIC_SPURIOUS: u64 = 0xf0;
+/// Software enable bit of the spurious interrupt vector register.
+const LAPIC_ENABLE: u32 = 1 << 8;
+
+// registres de l'I/O APIC, lus et ecrits a travers IOREGSEL et IOWIN
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION_TABLE: u32 = 0x10;
+const REDIRECTION_MASKED: u32 = 1 << 16;
+const REDIRECTION_ACTIVE_LOW: u32 = 1 << 13;
+const REDIRECTION_LEVEL: u32 = 1 << 15;
+
+/// An error that prevents using the APICs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicError {
+    /// No MADT was found in the ACPI tables.
+    NoMadt,
+    /// The MADT does not describe any I/O APIC.
+    NoIoApic,
+    /// No I/O APIC handles the global system interrupt.
+    NoSuchGsi(u32),
+    /// The registers could not be mapped.
+    Map(VmError),
+}
+
+impl From<VmError> for ApicError {
+    fn from(err: VmError) -> Self {
+        ApicError::Map(err)
+    }
+}
+
+/// An I/O APIC with its registers mapped.
+#[derive(Debug, Clone, Copy)]
+struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    unsafe fn read(&self, register: u32) -> u32 {
+        self.base.as_mut_ptr::<u32>().write_volatile(register);
+        (self.base + 0x10u64).as_ptr::<u32>().read_volatile()
+    }
+
+    unsafe fn write(&self, register: u32, value: u32) {
+        self.base.as_mut_ptr::<u32>().write_volatile(register);
+        (self.base + 0x10u64).as_mut_ptr::<u32>().write_volatile(value);
+    }
+
+    /// Masks every redirection entry.
+    unsafe fn mask_all(&self) {
+        for gsi in self.gsi_base..self.gsi_base + self.entries {
+            self.set_redirection(gsi, REDIRECTION_MASKED, 0);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        self.gsi_base <= gsi && gsi < self.gsi_base + self.entries
+    }
+
+    /// Writes the redirection entry of `gsi`, the high half (destination)
+    /// first so that the entry is never unmasked with a stale destination.
+    unsafe fn set_redirection(&self, gsi: u32, low: u32, high: u32) {
+        let register = IOAPIC_REDIRECTION_TABLE + 2 * (gsi - self.gsi_base);
+        self.write(register, REDIRECTION_MASKED);
+        self.write(register + 1, high);
+        self.write(register, low);
+    }
+}
+
+/// Virtual address of the local APIC registers, 0 while the APICs are not
+/// in use. Read by interrupt handlers, hence not behind a lock.
+static LOCAL_APIC: AtomicU64 = AtomicU64::new(0);
+
+struct Apics {
+    madt: Madt,
+    io_apics: [Option<IoApic>; MAX_IO_APICS],
+}
+
+static APICS: spin::Mutex<Option<Apics>> = spin::Mutex::new(None);
+
+/// Maps the page containing the registers at `phys` in a new `Mmio` region
+/// and returns the virtual address of `phys`.
+fn map_registers(phys: PhysAddr) -> Result<VirtAddr, VmError> {
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    let page_start = phys.align_down(4096u64);
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| {
+            let region = vmm.allocate(4096, RegionKind::Mmio, flags)?;
+            unsafe {
+                vmm.map_region_to(region.start, page_start, &mut memory.mapper, &mut memory.frame_allocator)
+            }
+            .inspect_err(|_| {
+                vmm.release(region.start).unwrap();
+            })
+        })
+    })
+    .ok_or(VmError::Uninitialized)?
+    .map(|region| region.start + (phys - page_start))
+}
+
+/// Unmaps and releases the region mapped by `map_registers` for `addr`.
+fn unmap_registers(addr: VirtAddr) {
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| {
+            let start = addr.align_down(4096u64);
+            // une region Mmio ne rend pas ses frames
+            unsafe { vmm.unmap_region(start, &mut memory.mapper, &mut memory.frame_allocator) }
+                .and_then(|_| vmm.release(start))
+                .expect("failed to release APIC registers");
+        })
+    });
+}
+
+unsafe fn local_apic_read(base: u64, register: u64) -> u32 {
+    ((base + register) as *const u32).read_volatile()
+}
+
+unsafe fn local_apic_write(base: u64, register: u64, value: u32) {
+    ((base + register) as *mut u32).write_volatile(value)
+}
+
+/// Masks every interrupt of the two 8259 PICs.
+fn mask_pics() {
+    let mut ports = [Port::<u8>::new(0x21), Port::<u8>::new(0xa1)];
+    for port in ports.iter_mut() {
+        unsafe { port.write(0xff) };
+    }
+}
+
+/// Maps the registers of the I/O APICs of `madt` into `io_apics`, masking
+/// all their entries, then those of the local APIC.
+///
+/// Returns the local APIC base. On error, `io_apics` holds the I/O APICs
+/// mapped so far.
+fn map_apics(madt: &Madt, io_apics: &mut [Option<IoApic>; MAX_IO_APICS]) -> Result<VirtAddr, VmError> {
+    for (slot, info) in io_apics.iter_mut().zip(madt.io_apics()) {
+        let mut io_apic = IoApic {
+            base: map_registers(info.address)?,
+            gsi_base: info.gsi_base,
+            entries: 0,
+        };
+        unsafe {
+            // bits 16 a 23 du registre de version : index de la derniere entree de redirection
+            io_apic.entries = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xff) + 1;
+            io_apic.mask_all();
+        }
+        *slot = Some(io_apic);
+    }
+    map_registers(madt.local_apic_address)
+}
+
+/// Sets up the local APIC and the I/O APICs described by the MADT, with
+/// every interrupt masked, and masks the 8259 PICs.
+///
+/// Interrupts must be disabled. Afterwards, interrupts are routed with
+/// `route_irq` and acknowledged with `end_of_interrupt`.
+pub fn init() -> Result<(), ApicError> {
+    let madt = acpi::madt().ok_or(ApicError::NoMadt)?;
+    if madt.io_apics().next().is_none() {
+        return Err(ApicError::NoIoApic);
+    }
+
+    let mut io_apics = [None; MAX_IO_APICS];
+    let local_apic = match map_apics(&madt, &mut io_apics) {
+        Ok(local_apic) => local_apic.as_u64(),
+        Err(err) => {
+            // les registres deja mappes ne serviront pas
+            for io_apic in io_apics.iter().flatten() {
+                unmap_registers(io_apic.base);
+            }
+            return Err(err.into());
+        }
+    };
+
+    mask_pics();
+    unsafe {
+        local_apic_write(local_apic, LAPIC_TASK_PRIORITY, 0);
+        local_apic_write(local_apic, LAPIC_SPURIOUS, LAPIC_ENABLE | u32::from(SPURIOUS_VECTOR));
+    }
+    *APICS.lock() = Some(Apics { madt, io_apics });
+    LOCAL_APIC.store(local_apic, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Masks every I/O APIC redirection entry and turns the local APIC off.
+///
+/// Interrupts must be disabled. The 8259 PICs stay masked until
+/// `irq::reroute` unmasks the lines that have handlers.
+pub fn disable() {
+    if let Some(apics) = APICS.lock().as_ref() {
+        for io_apic in apics.io_apics.iter().flatten() {
+            unsafe { io_apic.mask_all() };
+        }
+    }
+    let base = LOCAL_APIC.swap(0, Ordering::SeqCst);
+    if base != 0 {
+        unsafe { local_apic_write(base, LAPIC_SPURIOUS, u32::from(SPURIOUS_VECTOR)) };
+    }
+}
+
+/// Returns whether `init` succeeded, i.e. whether interrupts must be
+/// acknowledged through the local APIC.
+pub fn is_enabled() -> bool {
+    LOCAL_APIC.load(Ordering::Relaxed) != 0
+}
+
+/// Returns the APIC id of the current processor.
+pub fn local_apic_id() -> Option<u8> {
+    match LOCAL_APIC.load(Ordering::Relaxed) {
+        0 => None,
+        base => Some((unsafe { local_apic_read(base, LAPIC_ID) } >> 24) as u8),
+    }
+}
+
+/// Routes interrupt line `irq` to `vector` on the current processor, or
+/// masks it.
+///
+/// Lines 0 to 15 are the ISA interrupts (e.g. 0 for the PIT, 1 for the
+/// keyboard) and follow the MADT overrides; the next lines are global system
+/// interrupts of the I/O APICs, used by PCI devices.
+pub fn route_irq(irq: u8, vector: u8, masked: bool) -> Result<(), ApicError> {
+    let destination = local_apic_id().ok_or(ApicError::NoMadt)?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let apics = APICS.lock();
+        let apics = apics.as_ref().ok_or(ApicError::NoMadt)?;
+        let (gsi, source_override) = if irq < 16 {
+            apics.madt.isa_irq(irq)
+        } else {
+            (u32::from(irq), None)
+        };
+        let io_apic = apics
+            .io_apics
+            .iter()
+            .flatten()
+            .find(|io_apic| io_apic.handles(gsi))
+            .ok_or(ApicError::NoSuchGsi(gsi))?;
+
+        // sans surcharge, une interruption ISA est active haute et declenchee sur front, une interruption PCI active basse et
+        // declenchee sur niveau
+        let mut low = u32::from(vector);
+        match source_override {
+            Some(source_override) => {
+                if source_override.active_low() {
+                    low |= REDIRECTION_ACTIVE_LOW;
+                }
+                if source_override.level_triggered() {
+                    low |= REDIRECTION_LEVEL;
+                }
+            }
+            None if irq >= 16 => low |= REDIRECTION_ACTIVE_LOW | REDIRECTION_LEVEL,
+            None => {}
+        }
+        if masked {
+            low |= REDIRECTION_MASKED;
+        }
+        unsafe { io_apic.set_redirection(gsi, low, u32::from(destination) << 24) };
+        Ok(())
+    })
+}
+
+/// Signals the end of the current interrupt to the local APIC.
+pub fn end_of_interrupt() {
+    let base = LOCAL_APIC.load(Ordering::Relaxed);
+    if base != 0 {
+        unsafe { local_apic_write(base, LAPIC_EOI, 0) };
+    }
+}
@@ -0,0 +1,531 @@
+//Gestionnaires de toutes les exceptions du processeur. Une exception qui n'est pas resolue (breakpoint mis a part) aboutit dans
+//crash::crash avec un rapport decode : nom de l'exception, code d'erreur, et tous les registres au moment de la faute.
+//Pour que les registres generaux ne soient pas deja modifies par le prologue d'un gestionnaire Rust, chaque exception entre par
+//un petit stub assembleur qui les empile a cote du vecteur et du code d'erreur, puis appelle exception_entry. Une page fault
+//resolue (region paresseuse, copie a l'ecriture) revient dans le stub, qui restaure les registres et reprend l'instruction.
+//#DB et NMI ne sont pas des fautes : leur rapport est ecrit sur le port serie et l'execution reprend de la meme facon.
+//Les vecteurs 21 (#CP) et 28 (#HV) sont marques reserves par la crate x86_64 ; leurs entrees sont ecrites directement.
+
+pub use crate::crash::set_crash_hook;
+
+use crate::{crash, gdt, serial};
+use core::fmt::{self, Write};
+use x86_64::instructions::segmentation::{Segment, CS, SS};
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::registers::model_specific::Efer;
+use x86_64::structures::idt::{Entry, HandlerFunc, InterruptDescriptorTable, PageFaultErrorCode};
+use x86_64::VirtAddr;
+
+/// Mnemonic and name of each architectural exception, by vector.
+const EXCEPTIONS: [(&str, &str); 32] = [
+    ("#DE", "DIVIDE ERROR"),
+    ("#DB", "DEBUG"),
+    ("NMI", "NON-MASKABLE INTERRUPT"),
+    ("#BP", "BREAKPOINT"),
+    ("#OF", "OVERFLOW"),
+    ("#BR", "BOUND RANGE EXCEEDED"),
+    ("#UD", "INVALID OPCODE"),
+    ("#NM", "DEVICE NOT AVAILABLE"),
+    ("#DF", "DOUBLE FAULT"),
+    ("-", "COPROCESSOR SEGMENT OVERRUN"),
+    ("#TS", "INVALID TSS"),
+    ("#NP", "SEGMENT NOT PRESENT"),
+    ("#SS", "STACK-SEGMENT FAULT"),
+    ("#GP", "GENERAL PROTECTION FAULT"),
+    ("#PF", "PAGE FAULT"),
+    ("-", "RESERVED"),
+    ("#MF", "X87 FLOATING-POINT EXCEPTION"),
+    ("#AC", "ALIGNMENT CHECK"),
+    ("#MC", "MACHINE CHECK"),
+    ("#XM", "SIMD FLOATING-POINT EXCEPTION"),
+    ("#VE", "VIRTUALIZATION EXCEPTION"),
+    ("#CP", "CONTROL PROTECTION EXCEPTION"),
+    ("-", "RESERVED"),
+    ("-", "RESERVED"),
+    ("-", "RESERVED"),
+    ("-", "RESERVED"),
+    ("-", "RESERVED"),
+    ("-", "RESERVED"),
+    ("#HV", "HYPERVISOR INJECTION EXCEPTION"),
+    ("#VC", "VMM COMMUNICATION EXCEPTION"),
+    ("#SX", "SECURITY EXCEPTION"),
+    ("-", "RESERVED"),
+];
+
+/// Returns the mnemonic and the name of exception `vector`.
+pub fn exception_name(vector: u8) -> (&'static str, &'static str) {
+    EXCEPTIONS.get(usize::from(vector)).copied().unwrap_or(("-", "UNKNOWN"))
+}
+
+/// The descriptor table referenced by a selector error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    Gdt,
+    Idt,
+    Ldt,
+}
+
+/// The error code of #TS, #NP, #SS and #GP when the exception is related to
+/// a segment selector or an IDT entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorErrorCode {
+    /// The exception happened while delivering an external event.
+    pub external: bool,
+    pub table: DescriptorTable,
+    pub index: u16,
+}
+
+impl SelectorErrorCode {
+    pub fn new(error_code: u64) -> Self {
+        let table = match (error_code >> 1) & 0b11 {
+            0b00 => DescriptorTable::Gdt,
+            0b10 => DescriptorTable::Ldt,
+            _ => DescriptorTable::Idt,
+        };
+        SelectorErrorCode {
+            external: error_code & 1 != 0,
+            table,
+            index: ((error_code >> 3) & 0x1fff) as u16,
+        }
+    }
+}
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} index {:#x}", self.table, self.index)?;
+        if self.external {
+            write!(f, ", external event")?;
+        }
+        Ok(())
+    }
+}
+
+/// The error code of an exception, decoded according to its vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The exception does not push an error code.
+    None,
+    Selector(SelectorErrorCode),
+    PageFault(PageFaultErrorCode),
+    /// An error code with no selector (e.g. a #GP not caused by a segment),
+    /// or one that is not decoded.
+    Raw(u64),
+}
+
+/// The general purpose registers, in the order the exception stubs push
+/// them (the last pushed first).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct GeneralRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// What an exception stub leaves on the stack for `exception_entry`.
+#[repr(C)]
+struct ExceptionFrame {
+    registers: GeneralRegisters,
+    vector: u64,
+    /// 0 for the exceptions that do not push an error code.
+    error_code: u64,
+    // trame empilee par le processeur
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+/// The state of the CPU when a crash happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterSnapshot {
+    pub general: GeneralRegisters,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub efer: u64,
+}
+
+impl RegisterSnapshot {
+    /// Builds a snapshot from the registers saved by an exception stub and the
+    /// current control registers.
+    fn from_frame(frame: &ExceptionFrame) -> Self {
+        RegisterSnapshot {
+            general: frame.registers,
+            rip: frame.rip,
+            cs: frame.cs,
+            rflags: frame.rflags,
+            rsp: frame.rsp,
+            ss: frame.ss,
+            cr0: Cr0::read_raw(),
+            cr2: Cr2::read().as_u64(),
+            cr3: Cr3::read().0.start_address().as_u64(),
+            cr4: Cr4::read_raw(),
+            efer: Efer::read_raw(),
+        }
+    }
+
+    /// Reads the registers as they are in the caller, e.g. for a panic.
+    #[inline(always)]
+    pub fn current() -> Self {
+        let mut general = GeneralRegisters::default();
+        let (rip, rsp): (u64, u64);
+        unsafe {
+            // le registre qui recoit l'adresse de general perd sa valeur : la copie reste indicative
+            core::arch::asm!(
+                "mov [{0}], r15",
+                "mov [{0} + 8], r14",
+                "mov [{0} + 16], r13",
+                "mov [{0} + 24], r12",
+                "mov [{0} + 32], r11",
+                "mov [{0} + 40], r10",
+                "mov [{0} + 48], r9",
+                "mov [{0} + 56], r8",
+                "mov [{0} + 64], rbp",
+                "mov [{0} + 72], rdi",
+                "mov [{0} + 80], rsi",
+                "mov [{0} + 88], rdx",
+                "mov [{0} + 96], rcx",
+                "mov [{0} + 104], rbx",
+                "mov [{0} + 112], rax",
+                in(reg) &mut general,
+                options(nostack, preserves_flags),
+            );
+            core::arch::asm!("lea {}, [rip]", "mov {}, rsp", out(reg) rip, out(reg) rsp, options(nomem, nostack, preserves_flags));
+        }
+        RegisterSnapshot {
+            general,
+            rip,
+            cs: u64::from(CS::get_reg().0),
+            rflags: x86_64::registers::rflags::read_raw(),
+            rsp,
+            ss: u64::from(SS::get_reg().0),
+            cr0: Cr0::read_raw(),
+            cr2: Cr2::read().as_u64(),
+            cr3: Cr3::read().0.start_address().as_u64(),
+            cr4: Cr4::read_raw(),
+            efer: Efer::read_raw(),
+        }
+    }
+}
+
+const RFLAGS_NAMES: &[(u8, &str)] = &[
+    (0, "CF"), (2, "PF"), (4, "AF"), (6, "ZF"), (7, "SF"), (8, "TF"), (9, "IF"), (10, "DF"), (11, "OF"),
+    (14, "NT"), (16, "RF"), (17, "VM"), (18, "AC"), (19, "VIF"), (20, "VIP"), (21, "ID"),
+];
+const CR0_NAMES: &[(u8, &str)] = &[
+    (0, "PE"), (1, "MP"), (2, "EM"), (3, "TS"), (4, "ET"), (5, "NE"), (16, "WP"), (18, "AM"), (29, "NW"),
+    (30, "CD"), (31, "PG"),
+];
+const CR4_NAMES: &[(u8, &str)] = &[
+    (0, "VME"), (1, "PVI"), (2, "TSD"), (3, "DE"), (4, "PSE"), (5, "PAE"), (6, "MCE"), (7, "PGE"), (8, "PCE"),
+    (9, "OSFXSR"), (10, "OSXMMEXCPT"), (11, "UMIP"), (12, "LA57"), (13, "VMXE"), (14, "SMXE"), (16, "FSGSBASE"),
+    (17, "PCIDE"), (18, "OSXSAVE"), (20, "SMEP"), (21, "SMAP"), (22, "PKE"), (23, "CET"),
+];
+const EFER_NAMES: &[(u8, &str)] = &[
+    (0, "SCE"), (8, "LME"), (10, "LMA"), (11, "NXE"), (12, "SVME"), (13, "LMSLE"), (14, "FFXSR"), (15, "TCE"),
+];
+
+/// Displays the names of the bits set in `value`.
+pub struct FlagNames {
+    pub value: u64,
+    pub names: &'static [(u8, &'static str)],
+}
+
+impl fmt::Display for FlagNames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut set = self.names.iter().filter(|&&(bit, _)| self.value & (1 << bit) != 0);
+        match set.next() {
+            Some((_, first)) => write!(f, "{}", first)?,
+            None => return write!(f, "-"),
+        }
+        for (_, name) in set {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl RegisterSnapshot {
+    pub fn rflags_names(&self) -> FlagNames {
+        FlagNames { value: self.rflags, names: RFLAGS_NAMES }
+    }
+
+    pub fn cr0_names(&self) -> FlagNames {
+        FlagNames { value: self.cr0, names: CR0_NAMES }
+    }
+
+    pub fn cr4_names(&self) -> FlagNames {
+        FlagNames { value: self.cr4, names: CR4_NAMES }
+    }
+
+    pub fn efer_names(&self) -> FlagNames {
+        FlagNames { value: self.efer, names: EFER_NAMES }
+    }
+}
+
+impl fmt::Display for RegisterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let g = &self.general;
+        let rows = [
+            [("RAX", g.rax), ("RBX", g.rbx), ("RCX", g.rcx)],
+            [("RDX", g.rdx), ("RSI", g.rsi), ("RDI", g.rdi)],
+            [("RBP", g.rbp), ("RSP", self.rsp), ("R8 ", g.r8)],
+            [("R9 ", g.r9), ("R10", g.r10), ("R11", g.r11)],
+            [("R12", g.r12), ("R13", g.r13), ("R14", g.r14)],
+            [("R15", g.r15), ("RIP", self.rip), ("CR2", self.cr2)],
+        ];
+        for row in rows.iter() {
+            writeln!(f, "{} {:#018x}  {} {:#018x}  {} {:#018x}", row[0].0, row[0].1, row[1].0, row[1].1, row[2].0, row[2].1)?;
+        }
+        writeln!(f, "CS  {:#06x}  SS  {:#06x}  CR3 {:#018x}", self.cs, self.ss, self.cr3)?;
+        // IOPL occupe les bits 12 et 13 de RFLAGS
+        writeln!(f, "RFLAGS {:#010x} [{} IOPL={}]", self.rflags, self.rflags_names(), (self.rflags >> 12) & 0b11)?;
+        writeln!(f, "CR0 {:#010x} [{}]", self.cr0, self.cr0_names())?;
+        writeln!(f, "CR4 {:#010x} [{}]", self.cr4, self.cr4_names())?;
+        write!(f, "EFER {:#06x} [{}]", self.efer, self.efer_names())
+    }
+}
+
+/// Everything known about an exception that is reported.
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionReport {
+    pub vector: u8,
+    pub error_code: Option<u64>,
+    pub registers: RegisterSnapshot,
+    /// The kernel stack whose guard page was hit, for a #PF or a #DF.
+    pub overflowed_stack: Option<&'static str>,
+}
+
+impl ExceptionReport {
+    fn from_frame(frame: &ExceptionFrame) -> Self {
+        let vector = frame.vector as u8;
+        let error_code = match vector {
+            8 | 10..=14 | 17 | 21 | 29 | 30 => Some(frame.error_code),
+            _ => None,
+        };
+        // une double faute vient souvent d'une page fault sur une page de garde, CR2 en garde l'adresse
+        let overflowed_stack = match vector {
+            8 | 14 => crate::memory::stack::overflowed_stack(Cr2::read()),
+            _ => None,
+        };
+        ExceptionReport {
+            vector,
+            error_code,
+            registers: RegisterSnapshot::from_frame(frame),
+            overflowed_stack,
+        }
+    }
+
+    /// Decodes the error code according to the vector.
+    pub fn decoded_error_code(&self) -> ErrorCode {
+        match (self.vector, self.error_code) {
+            (_, None) => ErrorCode::None,
+            (10..=13, Some(code)) if code != 0 => ErrorCode::Selector(SelectorErrorCode::new(code)),
+            (14, Some(code)) => ErrorCode::PageFault(PageFaultErrorCode::from_bits_truncate(code)),
+            (_, Some(code)) => ErrorCode::Raw(code),
+        }
+    }
+}
+
+impl fmt::Display for ExceptionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (mnemonic, name) = exception_name(self.vector);
+        writeln!(f, "EXCEPTION: {} ({}, vector {})", name, mnemonic, self.vector)?;
+        if let Some(stack) = self.overflowed_stack {
+            writeln!(f, "KERNEL STACK OVERFLOW in stack '{}'", stack)?;
+        }
+        match self.decoded_error_code() {
+            ErrorCode::None => {}
+            ErrorCode::Selector(selector) => {
+                writeln!(f, "Error Code: {:#x} ({})", self.error_code.unwrap_or(0), selector)?
+            }
+            ErrorCode::PageFault(code) => {
+                writeln!(f, "Accessed Address: {:#x}", self.registers.cr2)?;
+                writeln!(f, "Error Code: {:?}", code)?
+            }
+            ErrorCode::Raw(code) => writeln!(f, "Error Code: {:#x}", code)?,
+        }
+        write!(f, "{}", self.registers)
+    }
+}
+
+//Un stub par exception : ceux des exceptions sans code d'erreur empilent 0 a sa place, pour que la trame ait toujours la forme
+//d'ExceptionFrame. Le processeur aligne la pile sur 16 octets avant d'empiler sa trame ; avec le code d'erreur, le vecteur et
+//les 15 registres (176 octets), elle est encore alignee au moment du call, comme l'exige l'ABI.
+core::arch::global_asm!(
+    ".macro exception_stub vector, has_error_code",
+    ".global exception_stub_\\vector",
+    "exception_stub_\\vector:",
+    ".if \\has_error_code == 0",
+    "    push 0",
+    ".endif",
+    "    push \\vector",
+    "    jmp exception_common",
+    ".endm",
+    "exception_common:",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    "    mov rdi, rsp",
+    "    cld",
+    "    call {entry}",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    // vecteur et code d'erreur
+    "    add rsp, 16",
+    "    iretq",
+    "exception_stub 0, 0",
+    "exception_stub 1, 0",
+    "exception_stub 2, 0",
+    "exception_stub 4, 0",
+    "exception_stub 5, 0",
+    "exception_stub 6, 0",
+    "exception_stub 7, 0",
+    "exception_stub 8, 1",
+    "exception_stub 10, 1",
+    "exception_stub 11, 1",
+    "exception_stub 12, 1",
+    "exception_stub 13, 1",
+    "exception_stub 14, 1",
+    "exception_stub 16, 0",
+    "exception_stub 17, 1",
+    "exception_stub 18, 0",
+    "exception_stub 19, 0",
+    "exception_stub 20, 0",
+    "exception_stub 21, 1",
+    "exception_stub 28, 0",
+    "exception_stub 29, 1",
+    "exception_stub 30, 1",
+    entry = sym exception_entry,
+);
+
+extern "C" {
+    fn exception_stub_0();
+    fn exception_stub_1();
+    fn exception_stub_2();
+    fn exception_stub_4();
+    fn exception_stub_5();
+    fn exception_stub_6();
+    fn exception_stub_7();
+    fn exception_stub_8();
+    fn exception_stub_10();
+    fn exception_stub_11();
+    fn exception_stub_12();
+    fn exception_stub_13();
+    fn exception_stub_14();
+    fn exception_stub_16();
+    fn exception_stub_17();
+    fn exception_stub_18();
+    fn exception_stub_19();
+    fn exception_stub_20();
+    fn exception_stub_21();
+    fn exception_stub_28();
+    fn exception_stub_29();
+    fn exception_stub_30();
+}
+
+/// Called by the exception stubs. Returns for a #DB, a NMI and a resolved
+/// page fault.
+extern "C" fn exception_entry(frame: &ExceptionFrame) {
+    match frame.vector {
+        1 | 2 => report(&ExceptionReport::from_frame(frame)),
+        14 if super::resolve_page_fault(PageFaultErrorCode::from_bits_truncate(frame.error_code)) => {}
+        _ => crash::crash(&ExceptionReport::from_frame(frame)),
+    }
+}
+
+/// Writes the report of an exception that is not fatal to the serial port.
+fn report(report: &ExceptionReport) {
+    // un NMI peut arriver pendant une ecriture sur le port serie : sans le verrou, le rapport est perdu plutot que de bloquer
+    if let Some(mut serial) = serial::SERIAL1.try_lock() {
+        let _ = writeln!(serial, "{}", report);
+    }
+}
+
+/// Returns the address of an exception stub, for the IDT.
+fn stub_addr(stub: unsafe extern "C" fn()) -> VirtAddr {
+    VirtAddr::new(stub as usize as u64)
+}
+
+/// Returns the IDT entry of a vector the x86_64 crate marks as reserved.
+fn reserved_entry(idt: &mut InterruptDescriptorTable, vector: usize) -> &mut Entry<HandlerFunc> {
+    assert!(vector < 32, "vector {} is not an exception", vector);
+    // la table est repr(C) : ses 32 premieres entrees sont les exceptions, dans l'ordre des vecteurs
+    unsafe { &mut *(idt as *mut InterruptDescriptorTable as *mut Entry<HandlerFunc>).add(vector) }
+}
+
+/// Installs the exception stubs in the IDT, for every exception except the
+/// breakpoint, which `interrupts` handles itself.
+pub(super) fn install(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        reserved_entry(idt, 21).set_handler_addr(stub_addr(exception_stub_21));
+        reserved_entry(idt, 28).set_handler_addr(stub_addr(exception_stub_28));
+        idt.divide_error.set_handler_addr(stub_addr(exception_stub_0));
+        idt.debug.set_handler_addr(stub_addr(exception_stub_1));
+        idt.non_maskable_interrupt.set_handler_addr(stub_addr(exception_stub_2));
+        idt.overflow.set_handler_addr(stub_addr(exception_stub_4));
+        idt.bound_range_exceeded.set_handler_addr(stub_addr(exception_stub_5));
+        idt.invalid_opcode.set_handler_addr(stub_addr(exception_stub_6));
+        idt.device_not_available.set_handler_addr(stub_addr(exception_stub_7));
+        idt.double_fault
+            .set_handler_addr(stub_addr(exception_stub_8))
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.invalid_tss.set_handler_addr(stub_addr(exception_stub_10));
+        idt.segment_not_present.set_handler_addr(stub_addr(exception_stub_11));
+        idt.stack_segment_fault.set_handler_addr(stub_addr(exception_stub_12));
+        idt.general_protection_fault.set_handler_addr(stub_addr(exception_stub_13));
+        idt.page_fault.set_handler_addr(stub_addr(exception_stub_14));
+        idt.x87_floating_point.set_handler_addr(stub_addr(exception_stub_16));
+        idt.alignment_check.set_handler_addr(stub_addr(exception_stub_17));
+        idt.machine_check.set_handler_addr(stub_addr(exception_stub_18));
+        idt.simd_floating_point.set_handler_addr(stub_addr(exception_stub_19));
+        idt.virtualization.set_handler_addr(stub_addr(exception_stub_20));
+        idt.vmm_communication_exception.set_handler_addr(stub_addr(exception_stub_29));
+        idt.security_exception.set_handler_addr(stub_addr(exception_stub_30));
+    }
+}
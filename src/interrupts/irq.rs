@@ -0,0 +1,361 @@
+//Enregistrement dynamique des gestionnaires d'interruptions materielles. Chaque ligne d'interruption (IRQ) a son propre vecteur,
+//PIC_1_OFFSET + numero de ligne, et une entree de l'IDT qui appelle dispatch : les gestionnaires enregistres sur la ligne sont
+//appeles tour a tour, puis la fin de l'interruption est signalee au controleur actif. Ajouter un pilote ne demande donc plus de
+//modifier l'IDT. Les lignes 0 a 15 sont les interruptions ISA, les suivantes ne sont accessibles qu'avec l'APIC.
+//Une ligne sans gestionnaire, ou masquee par mask, est masquee dans le controleur.
+//dispatch copie les gestionnaires de la ligne puis relache le verrou avant de les appeler : un gestionnaire peut donc utiliser
+//mask, unmask et has_handlers. register et unregister y sont refuses (IrqError::InHandler), car ils modifieraient les
+//gestionnaires en cours d'appel.
+
+use super::{apic, InterruptController, PIC_1_OFFSET, PICS};
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+use x86_64::structures::idt::{HandlerFunc, InterruptStackFrame};
+
+/// Number of interrupt lines, using vectors `PIC_1_OFFSET` to
+/// `PIC_1_OFFSET + MAX_IRQS - 1`.
+pub const MAX_IRQS: usize = 32;
+
+/// Number of lines handled by the two 8259 PICs.
+pub const PIC_IRQS: u8 = 16;
+
+/// Maximal number of handlers sharing one line.
+pub const MAX_SHARED_HANDLERS: usize = 4;
+
+/// The line connecting the second 8259 PIC to the first one.
+const CASCADE_IRQ: u8 = 2;
+
+/// Whether a line may have other handlers than the one being registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sharing {
+    /// The handler must be alone on its line.
+    Exclusive,
+    /// The handler accepts other `Shared` handlers on its line. Every handler
+    /// of the line is called for each interrupt, so each one must check
+    /// whether its device raised it.
+    Shared,
+}
+
+/// An error returned by the registration functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The line is not below `MAX_IRQS`.
+    NoSuchLine(u8),
+    /// The active controller cannot deliver this line, e.g. a line above 15
+    /// with the 8259 PICs.
+    NotRoutable(u8),
+    /// The line has an exclusive handler, or an exclusive handler was asked
+    /// for a line that has handlers already.
+    Busy(u8),
+    /// The line has `MAX_SHARED_HANDLERS` handlers already.
+    TooManyHandlers(u8),
+    /// The handler was unregistered already.
+    NoSuchHandler,
+    /// The I/O APIC could not route the line.
+    Apic(apic::ApicError),
+    /// Handlers cannot be registered or unregistered from a handler.
+    InHandler,
+}
+
+impl From<apic::ApicError> for IrqError {
+    fn from(err: apic::ApicError) -> Self {
+        IrqError::Apic(err)
+    }
+}
+
+/// Identifies a registered handler, to unregister it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerId {
+    line: u8,
+    id: u32,
+}
+
+impl HandlerId {
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+}
+
+enum Handler {
+    Function(fn()),
+    Closure(Box<dyn Fn() + Send + Sync>),
+}
+
+impl Handler {
+    fn call(&self) {
+        match self {
+            Handler::Function(function) => function(),
+            Handler::Closure(closure) => closure(),
+        }
+    }
+}
+
+struct Line {
+    handlers: [Option<(u32, Handler)>; MAX_SHARED_HANDLERS],
+    sharing: Sharing,
+    masked: bool,
+}
+
+impl Line {
+    fn has_handlers(&self) -> bool {
+        self.handlers.iter().any(|slot| slot.is_some())
+    }
+
+    /// Whether the controller must deliver the interrupts of this line.
+    fn is_enabled(&self) -> bool {
+        self.has_handlers() && !self.masked
+    }
+}
+
+const NO_HANDLER: Option<(u32, Handler)> = None;
+const EMPTY_LINE: Line = Line {
+    handlers: [NO_HANDLER; MAX_SHARED_HANDLERS],
+    sharing: Sharing::Shared,
+    masked: false,
+};
+
+//le verrou n'est pris qu'avec les interruptions desactivees : un gestionnaire ne peut pas l'attendre
+static LINES: spin::Mutex<[Line; MAX_IRQS]> = spin::Mutex::new([EMPTY_LINE; MAX_IRQS]);
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+/// Set while `dispatch` calls handlers.
+static IN_HANDLER: AtomicBool = AtomicBool::new(false);
+
+/// Returns the vector of interrupt line `line`.
+pub fn vector(line: u8) -> u8 {
+    PIC_1_OFFSET + line
+}
+
+/// Sets or clears the mask bit of `line` in the 8259 PICs.
+fn set_pic_mask(line: u8, masked: bool) {
+    let (mut port, bit) = if line < 8 {
+        (Port::<u8>::new(0x21), line)
+    } else {
+        (Port::<u8>::new(0xa1), line - 8)
+    };
+    unsafe {
+        let mask = port.read();
+        port.write(if masked { mask | 1 << bit } else { mask & !(1 << bit) });
+    }
+}
+
+/// Programs the active controller for `line`.
+fn apply(line: u8, state: &Line) -> Result<(), IrqError> {
+    match super::active_controller() {
+        InterruptController::Apic => Ok(apic::route_irq(line, vector(line), !state.is_enabled())?),
+        // une ligne au-dela des PIC reste simplement inaccessible
+        InterruptController::Pic if line >= PIC_IRQS => Ok(()),
+        InterruptController::Pic => {
+            set_pic_mask(line, !state.is_enabled());
+            Ok(())
+        }
+    }
+}
+
+/// Masks every line of the 8259 PICs except the cascade line.
+///
+/// Called by `blog_os::init`, before any handler is registered.
+pub fn init() {
+    without_interrupts(|| {
+        let mut ports = [Port::<u8>::new(0x21), Port::<u8>::new(0xa1)];
+        for port in ports.iter_mut() {
+            unsafe { port.write(0xff) };
+        }
+        set_pic_mask(CASCADE_IRQ, false);
+    });
+}
+
+/// Programs every line in the active controller, after a controller switch.
+///
+/// Interrupts must be disabled.
+pub(super) fn reroute() -> Result<(), IrqError> {
+    if super::active_controller() == InterruptController::Pic {
+        init();
+    }
+    let lines = LINES.lock();
+    for (line, state) in lines.iter().enumerate() {
+        apply(line as u8, state)?;
+    }
+    Ok(())
+}
+
+fn register_handler(line: u8, sharing: Sharing, handler: Handler) -> Result<HandlerId, IrqError> {
+    if usize::from(line) >= MAX_IRQS {
+        return Err(IrqError::NoSuchLine(line));
+    }
+    if line >= PIC_IRQS && super::active_controller() == InterruptController::Pic {
+        return Err(IrqError::NotRoutable(line));
+    }
+    if IN_HANDLER.load(Ordering::Relaxed) {
+        return Err(IrqError::InHandler);
+    }
+    without_interrupts(|| {
+        let mut lines = LINES.lock();
+        let state = &mut lines[usize::from(line)];
+        let had_handlers = state.has_handlers();
+        if had_handlers && (sharing == Sharing::Exclusive || state.sharing == Sharing::Exclusive) {
+            return Err(IrqError::Busy(line));
+        }
+        let slot = state
+            .handlers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(IrqError::TooManyHandlers(line))?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        *slot = Some((id, handler));
+        state.sharing = sharing;
+
+        if !had_handlers {
+            if let Err(err) = apply(line, state) {
+                state.handlers = [NO_HANDLER; MAX_SHARED_HANDLERS];
+                return Err(err);
+            }
+        }
+        Ok(HandlerId { line, id })
+    })
+}
+
+/// Installs `handler` for interrupt line `line` and unmasks the line, unless
+/// `mask` was called for it.
+///
+/// The handler runs with interrupts disabled, after the interrupt was
+/// raised and before it is acknowledged. It may call `mask`, `unmask` and
+/// `has_handlers`, but `register` and `unregister` fail with
+/// `IrqError::InHandler`.
+pub fn register(line: u8, sharing: Sharing, handler: fn()) -> Result<HandlerId, IrqError> {
+    register_handler(line, sharing, Handler::Function(handler))
+}
+
+/// Same as `register`, with a closure. Requires the heap.
+pub fn register_closure<F>(line: u8, sharing: Sharing, handler: F) -> Result<HandlerId, IrqError>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    register_handler(line, sharing, Handler::Closure(Box::new(handler)))
+}
+
+/// Removes a handler installed by `register` or `register_closure`. The line
+/// is masked when its last handler is removed.
+pub fn unregister(handler: HandlerId) -> Result<(), IrqError> {
+    if IN_HANDLER.load(Ordering::Relaxed) {
+        return Err(IrqError::InHandler);
+    }
+    let removed = without_interrupts(|| {
+        let mut lines = LINES.lock();
+        let state = &mut lines[usize::from(handler.line)];
+        let slot = state
+            .handlers
+            .iter_mut()
+            .find(|slot| matches!(slot, Some((id, _)) if *id == handler.id))
+            .ok_or(IrqError::NoSuchHandler)?;
+        let removed = slot.take();
+        if !state.has_handlers() {
+            state.sharing = Sharing::Shared;
+            apply(handler.line, state)?;
+        }
+        Ok::<_, IrqError>(removed)
+    })?;
+    // une fermeture est liberee hors du verrou, interruptions actives
+    drop(removed);
+    Ok(())
+}
+
+fn set_masked(line: u8, masked: bool) -> Result<(), IrqError> {
+    if usize::from(line) >= MAX_IRQS {
+        return Err(IrqError::NoSuchLine(line));
+    }
+    without_interrupts(|| {
+        let mut lines = LINES.lock();
+        let state = &mut lines[usize::from(line)];
+        state.masked = masked;
+        apply(line, state)
+    })
+}
+
+/// Stops the delivery of the interrupts of `line` until `unmask` is called,
+/// whatever its handlers.
+pub fn mask(line: u8) -> Result<(), IrqError> {
+    set_masked(line, true)
+}
+
+/// Delivers the interrupts of `line` again if it has handlers.
+pub fn unmask(line: u8) -> Result<(), IrqError> {
+    set_masked(line, false)
+}
+
+/// Returns whether `line` has at least one handler.
+pub fn has_handlers(line: u8) -> bool {
+    usize::from(line) < MAX_IRQS && without_interrupts(|| LINES.lock()[usize::from(line)].has_handlers())
+}
+
+/// Reads the in-service register of the 8259 PIC at `command_port`.
+fn pic_in_service(command_port: u16) -> u8 {
+    let mut port = Port::<u8>::new(command_port);
+    unsafe {
+        // OCW3 : la prochaine lecture du port de commande rend l'ISR
+        port.write(0x0b);
+        port.read()
+    }
+}
+
+/// Returns whether an interrupt of the 8259 PICs on `line` is spurious, and
+/// acknowledges the cascade line for a spurious interrupt of the second PIC.
+///
+/// A PIC raises its lowest priority line (7 or 15) when the request that
+/// started the acknowledge cycle went away; the line is then not set in its
+/// in-service register and must not get an end of interrupt.
+fn is_spurious(line: u8) -> bool {
+    match line {
+        7 => pic_in_service(0x20) & 0x80 == 0,
+        15 if pic_in_service(0xa0) & 0x80 == 0 => {
+            // le premier PIC a bien servi la ligne de cascade
+            unsafe { Port::<u8>::new(0x20).write(0x20) };
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Calls the handlers of `line` and acknowledges the interrupt.
+fn dispatch(line: u8) {
+    if !apic::is_enabled() && is_spurious(line) {
+        return;
+    }
+
+    let mut handlers: [Option<*const Handler>; MAX_SHARED_HANDLERS] = [None; MAX_SHARED_HANDLERS];
+    {
+        let lines = LINES.lock();
+        let registered = lines[usize::from(line)].handlers.iter().flatten();
+        for (slot, (_, handler)) in handlers.iter_mut().zip(registered) {
+            *slot = Some(handler as *const Handler);
+        }
+    }
+    // les gestionnaires restent a leur place dans LINES : register et unregister sont refuses tant que IN_HANDLER est mis
+    IN_HANDLER.store(true, Ordering::Relaxed);
+    for &handler in handlers.iter().flatten() {
+        unsafe { (*handler).call() };
+    }
+    IN_HANDLER.store(false, Ordering::Relaxed);
+
+    if apic::is_enabled() {
+        apic::end_of_interrupt();
+    } else {
+        unsafe { PICS.lock().notify_end_of_interrupt(vector(line)) };
+    }
+}
+
+extern "x86-interrupt" fn irq_stub<const LINE: u8>(_stack_frame: InterruptStackFrame) {
+    dispatch(LINE);
+}
+
+macro_rules! irq_stubs {
+    ($($line:literal)*) => {
+        [$(irq_stub::<$line> as HandlerFunc),*]
+    };
+}
+
+/// The IDT entry of each line, installed at `vector(line)`.
+pub(super) static STUBS: [HandlerFunc; MAX_IRQS] = irq_stubs!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+    16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+);
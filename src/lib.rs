@@ -13,6 +13,12 @@ pub mod interrupts;
 pub mod serial;
 pub mod vga_buffer;
 pub mod memory;
+//SMEP, SMAP et UMIP
+pub mod cpu;
+//tables ACPI (MADT, FADT, HPET)
+pub mod acpi;
+//rapport et page de plantage sur les erreurs fatales
+pub mod crash;
 extern crate alloc;
 pub mod allocator;
 
@@ -30,9 +36,13 @@ entry_point!(test_kernel_main);
 pub fn init() {
     gdt::init();
     interrupts::init_idt();
+    //le noyau ne doit ni executer ni lire par megarde les pages utilisateur
+    cpu::enable_protections();
     //interruption materiel avec pics
     //Nous utilisons la initializefonction pour effectuer l'initialisation du PIC. Comme la ChainedPics::newfonction, cette fonction est également dangereuse car elle peut provoquer un comportement indéfini si le PIC est mal configuré.
     unsafe { interrupts::PICS.lock().initialize() }; 
+    //seules les lignes qui ont un gestionnaire (minuteur et clavier pour l'instant) restent demasquees
+    interrupts::init_irqs();
     //activations des interruptions 
     x86_64::instructions::interrupts::enable();  
 }
@@ -105,7 +115,10 @@ pub fn hlt_loop() -> ! {
 
 #[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
-    panic!("allocation error: {:?}", layout)
+    //les fonctions de recuperation ont deja ete appelees par l'allocateur global, il ne reste qu'a expliquer pourquoi on s'arrete
+    allocator::oom::report(layout);
+    x86_64::instructions::interrupts::disable();
+    hlt_loop()
 }
 
 /// Entry point for `cargo test`
@@ -21,11 +21,15 @@ use blog_os::task::{Task};
 use blog_os::task::executor::Executor;
 use blog_os::task::keyboard;
 
+//Controleur d'interruption choisi au demarrage : Apic (avec retour aux PIC si la machine n'en a pas) ou Pic pour garder les 8259.
+const INTERRUPT_CONTROLLER: blog_os::interrupts::InterruptController = blog_os::interrupts::InterruptController::Apic;
+
 //Nous n'avons plus besoin d'utiliser extern "C"ou no_manglepour notre point d'entrée, car la macro définit _startpour nous le véritable point d'entrée de niveau inférieur
 //kernel_mainfonction est maintenant une fonction Rust tout à fait normale, nous pouvons donc lui choisir un nom arbitraire.
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     use blog_os::allocator; 
-    use blog_os::memory::{self, BootInfoFrameAllocator};
+    use blog_os::interrupts;
+    use blog_os::memory::{self, BitmapFrameAllocator};
     use x86_64::{VirtAddr};
 
     println!("Hello World{}", "!");
@@ -33,10 +37,39 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    //le code du noyau n'est plus inscriptible et ses donnees ne sont plus executables (W^X)
+    unsafe { memory::protect::remap_kernel(&mut mapper, &boot_info.memory_map) }
+        .expect("failed to remap the kernel sections");
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    //bilan de la memoire physique sur le port serie
+    blog_os::serial_println!("{}", memory::report::MemoryReport::new(&boot_info.memory_map));
 
     allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");//Dans le cas où la init_heapfonction renvoie une erreur, nous paniquons en utilisant la Result::expectméthode car il n'y a actuellement aucun moyen sensé pour nous de gérer cette erreur.
+    //le tas peut maintenant grandir a la demande en mappant de nouvelles pages
+    memory::init_kernel_memory(mapper, frame_allocator);
+    //les piles des gestionnaires de faute ont maintenant une page de garde
+    memory::stack::init_interrupt_stacks().expect("failed to allocate the interrupt stacks");
+    //les tables ACPI sont decodees avant que leurs regions ne soient recuperees
+    match blog_os::acpi::init() {
+        Ok(tables) => blog_os::serial_println!(
+            "ACPI revision {}: MADT {}, FADT {}, HPET {}",
+            tables.rsdp.revision,
+            tables.madt.is_some(),
+            tables.fadt.is_some(),
+            tables.hpet.is_some(),
+        ),
+        Err(err) => println!("ACPI tables not found: {:?}", err),
+    }
+    //les interruptions materielles passent par l'APIC si la MADT en decrit un, sinon par les PIC
+    let controller = interrupts::select_controller(INTERRUPT_CONTROLLER);
+    println!("interrupt controller: {:?}", controller);
+    //les tables ACPI ont ete decodees : leurs frames rejoignent les frames libres
+    let reclaimed = memory::with_kernel_memory(|memory| unsafe {
+        memory::report::reclaim_boot_memory(&boot_info.memory_map, &mut memory.frame_allocator)
+    });
+    blog_os::serial_println!("reclaimed {} KiB of boot memory", reclaimed.unwrap_or(0) * 4);
 
         
     //multitache
@@ -64,8 +97,8 @@ async fn example_task() {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     //Le paramètre PanicInfo contient le fichier et la ligne où le panic a eu lieu et le message optionnel de panic.
-    println!("{}", info);
-    blog_os::hlt_loop();
+    //il est affiche avec tous les registres sur le port serie et sur une page de plantage
+    blog_os::crash::panic(info)
 }
 
 //utilisation de la panic test
@@ -1,11 +1,34 @@
-use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use bootloader::bootinfo::{MemoryMap, MemoryRegion, MemoryRegionType};
+use core::sync::atomic::{AtomicU64, Ordering};
 use x86_64::{
     structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+        PhysFrame, Size2MiB, Size4KiB,
     },
     PhysAddr, VirtAddr,
 };
 
+//allocateur de blocs de frames contigues (systeme buddy)
+pub mod buddy;
+//gestionnaire des plages d'adresses virtuelles du noyau
+pub mod vmm;
+//piles du noyau avec page de garde
+pub mod stack;
+//pages de 2 MiB et 1 GiB
+pub mod huge;
+//parcours des tables de pages actives
+pub mod walk;
+//protection W^X : pages non executables et droits des sections du noyau
+pub mod protect;
+//copies depuis et vers la memoire utilisateur
+pub mod user;
+//partage de frames en copie a l'ecriture
+pub mod cow;
+//espaces d'adressage separes qui partagent les mappages du noyau
+pub mod address_space;
+//bilan de la carte memoire du bootloader
+pub mod report;
+
 /// Initialize a new OffsetPageTable.
 ///
 /// This function is unsafe because the caller must guarantee that the
@@ -13,10 +36,57 @@ use x86_64::{
 /// `physical_memory_offset`. Also, this function must be only called once
 /// to avoid aliasing `&mut` references (which is undefined behavior).
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
+    //les regions du noyau (tas, piles) sont mappees avec NO_EXECUTE, qui doit etre active avant le premier mappage
+    protect::enable_nx();
     let level_4_table = active_level_4_table(physical_memory_offset);
+    vmm::with_vmm(|vmm| vmm.reserve_used_entries(level_4_table));
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+//l'offset 0 n'est jamais utilise par le bootloader (les adresses basses sont celles du noyau), il signifie "pas encore initialise"
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the virtual address at which the complete physical memory is
+/// mapped, or `None` if `init` was not called yet.
+pub fn physical_memory_offset() -> Option<VirtAddr> {
+    match PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) {
+        0 => None,
+        offset => Some(VirtAddr::new(offset)),
+    }
+}
+
+/// The kernel's page table together with the frame allocator backing it.
+///
+/// Code that has to map memory on its own after boot (e.g. the heap when it
+/// grows) reaches them through `with_kernel_memory`.
+pub struct KernelMemory {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: BitmapFrameAllocator,
+}
+
+//Ordre des verrous : KERNEL_MEMORY, puis le VMM (vmm::with_vmm), puis le tas. Le verrou du tas n'est jamais tenu pendant que
+//les deux autres sont pris, le tas grandit apres l'avoir relache.
+static KERNEL_MEMORY: spin::Mutex<Option<KernelMemory>> = spin::Mutex::new(None);
+
+/// Hands the kernel's page table and frame allocator over to the global
+/// `KernelMemory`.
+pub fn init_kernel_memory(mapper: OffsetPageTable<'static>, frame_allocator: BitmapFrameAllocator) {
+    *KERNEL_MEMORY.lock() = Some(KernelMemory {
+        mapper,
+        frame_allocator,
+    });
+}
+
+/// Runs `f` with exclusive access to the global `KernelMemory`.
+///
+/// Returns `None` if `init_kernel_memory` was not called yet. Interrupts are
+/// disabled while the lock is held so that an interrupt handler cannot
+/// deadlock on it.
+pub fn with_kernel_memory<R>(f: impl FnOnce(&mut KernelMemory) -> R) -> Option<R> {
+    x86_64::instructions::interrupts::without_interrupts(|| KERNEL_MEMORY.lock().as_mut().map(f))
+}
+
 /// Returns a mutable reference to the active level 4 table.
 ///
 /// This function is unsafe because the caller must guarantee that the
@@ -44,7 +114,7 @@ pub fn create_example_mapping(
     use x86_64::structures::paging::PageTableFlags as Flags;
 
     let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-    let flags = Flags::PRESENT | Flags::WRITABLE;
+    let flags = Flags::PRESENT | Flags::WRITABLE | Flags::NO_EXECUTE;
 
     let map_to_result = unsafe {
         // FIXME: this is not safe, we do it only for testing
@@ -101,4 +171,233 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
         self.next += 1;
         frame
     }
-}
\ No newline at end of file
+}
+/// Returns the first usable region of the memory map that can hold `size` bytes.
+fn find_usable_region(memory_map: &MemoryMap, size: u64) -> Option<&MemoryRegion> {
+    memory_map.iter().find(|r| {
+        r.region_type == MemoryRegionType::Usable
+            && r.range.end_addr() - r.range.start_addr() >= size
+    })
+}
+
+/// A FrameAllocator that keeps one bit per physical frame, so that frames can be
+/// handed back through `FrameDeallocator`.
+///
+/// The bitmap itself lives in the first usable region large enough to hold it and
+/// is accessed through the physical memory mapping. A set bit means the frame is
+/// in use (or not usable at all). A second bitmap of the same size records which
+/// frames are usable RAM, so that only those can be deallocated.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    usable: &'static mut [u64],
+    usable_frames: usize,
+    used_frames: usize,
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Create a BitmapFrameAllocator from the passed memory map.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the passed memory map is valid, that all
+    /// frames marked as `USABLE` in it are really unused and that the complete
+    /// physical memory is mapped at the passed `physical_memory_offset`.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        // le bitmap couvre toutes les frames jusqu'a la fin de la derniere region utilisable
+        let frame_count = usable_regions()
+            .map(|r| r.range.end_frame_number as usize)
+            .max()
+            .unwrap_or(0);
+        let words = frame_count.div_ceil(64);
+        // le bitmap des frames utilisees puis celui des frames utilisables
+        let bitmap_size = (2 * words * 8) as u64;
+        let bitmap_frames = bitmap_size.div_ceil(4096);
+
+        let bitmap_region = find_usable_region(memory_map, bitmap_frames * 4096)
+            .expect("no usable region large enough for the frame bitmap");
+        let bitmap_ptr =
+            (physical_memory_offset + bitmap_region.range.start_addr()).as_mut_ptr::<u64>();
+        let bitmap = core::slice::from_raw_parts_mut(bitmap_ptr, words);
+        let usable = core::slice::from_raw_parts_mut(bitmap_ptr.add(words), words);
+
+        // every frame starts as used, then the usable ones are released
+        bitmap.fill(!0);
+        usable.fill(0);
+        let mut allocator = BitmapFrameAllocator {
+            bitmap,
+            usable,
+            usable_frames: 0,
+            used_frames: 0,
+            next: 0,
+        };
+        for region in usable_regions() {
+            for index in region.range.start_frame_number..region.range.end_frame_number {
+                allocator.clear(index as usize);
+                allocator.set_usable(index as usize, true);
+                allocator.usable_frames += 1;
+            }
+        }
+
+        // the frames holding the bitmap must never be handed out, nor given back by deallocate_frame
+        let first = bitmap_region.range.start_frame_number as usize;
+        for index in first..first + bitmap_frames as usize {
+            allocator.set(index);
+            allocator.set_usable(index, false);
+            allocator.used_frames += 1;
+        }
+
+        allocator
+    }
+
+    /// Gives the frames of every region of type `kind` to the allocator, and
+    /// returns how many frames were added.
+    ///
+    /// Frames past the end of the bitmap (i.e. above the last usable region)
+    /// are left out.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the content of these regions is not
+    /// needed anymore and that they were not reclaimed before. `kind` must not
+    /// be `Usable`.
+    pub unsafe fn reclaim(&mut self, memory_map: &MemoryMap, kind: MemoryRegionType) -> usize {
+        assert!(kind != MemoryRegionType::Usable, "usable regions are part of the allocator already");
+        let bitmap_frames = self.bitmap.len() * 64;
+        let mut reclaimed = 0;
+        for region in memory_map.iter().filter(|r| r.region_type == kind) {
+            let start = region.range.start_frame_number as usize;
+            let end = (region.range.end_frame_number as usize).min(bitmap_frames);
+            for index in start..end {
+                // la frame 0 reste reservee : une adresse physique nulle ressemble trop a une erreur
+                if index != 0 && !self.is_usable(index) {
+                    self.clear(index);
+                    self.set_usable(index, true);
+                    self.usable_frames += 1;
+                    reclaimed += 1;
+                }
+            }
+        }
+        reclaimed
+    }
+
+    /// Returns the number of usable frames reported by the memory map.
+    pub fn total_frames(&self) -> usize {
+        self.usable_frames
+    }
+
+    /// Returns the number of usable frames currently allocated, including the
+    /// frames occupied by the bitmap itself.
+    pub fn used_frames(&self) -> usize {
+        self.used_frames
+    }
+
+    /// Returns the number of frames that can still be allocated.
+    pub fn free_frames(&self) -> usize {
+        self.usable_frames - self.used_frames
+    }
+
+    fn is_set(&self, index: usize) -> bool {
+        self.bitmap[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set(&mut self, index: usize) {
+        self.bitmap[index / 64] |= 1 << (index % 64);
+    }
+
+    fn clear(&mut self, index: usize) {
+        self.bitmap[index / 64] &= !(1 << (index % 64));
+    }
+
+    /// Returns whether frame `index` is usable RAM managed by the allocator.
+    fn is_usable(&self, index: usize) -> bool {
+        self.usable
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    fn set_usable(&mut self, index: usize, usable: bool) {
+        if usable {
+            self.usable[index / 64] |= 1 << (index % 64);
+        } else {
+            self.usable[index / 64] &= !(1 << (index % 64));
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let words = self.bitmap.len();
+        // next-fit: start at the word where the last free frame was found
+        for offset in 0..words {
+            let word = (self.next + offset) % words;
+            if self.bitmap[word] != !0 {
+                let index = word * 64 + self.bitmap[word].trailing_ones() as usize;
+                self.set(index);
+                self.used_frames += 1;
+                self.next = word;
+                let addr = PhysAddr::new(index as u64 * 4096);
+                return Some(PhysFrame::containing_address(addr));
+            }
+        }
+        None
+    }
+}
+
+//Seules les frames de RAM utilisable peuvent etre rendues : liberer une frame reservee, MMIO ou du tampon VGA, qu'une table de
+//pages peut aussi mapper, est une erreur de l'appelant au meme titre qu'une double liberation.
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let index = (frame.start_address().as_u64() / 4096) as usize;
+        assert!(self.is_usable(index), "deallocating frame {:?} that is not usable RAM", frame);
+        assert!(self.is_set(index), "deallocating frame {:?} that is not allocated", frame);
+        self.clear(index);
+        self.used_frames -= 1;
+        self.next = self.next.min(index / 64);
+    }
+}
+
+/// Number of bitmap words covering one `Size2MiB` frame (512 frames).
+const WORDS_PER_HUGE_FRAME: usize = 512 / 64;
+
+//Une frame de 2 MiB alignee correspond exactement a 8 mots consecutifs du bitmap, qui doivent tous etre a zero (frames libres).
+unsafe impl FrameAllocator<Size2MiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let huge_index = self
+            .bitmap
+            .chunks_exact(WORDS_PER_HUGE_FRAME)
+            .position(|words| words.iter().all(|&word| word == 0))?;
+        let first_word = huge_index * WORDS_PER_HUGE_FRAME;
+        self.bitmap[first_word..first_word + WORDS_PER_HUGE_FRAME].fill(!0);
+        self.used_frames += 512;
+        let addr = PhysAddr::new(huge_index as u64 * Size2MiB::SIZE);
+        Some(PhysFrame::containing_address(addr))
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let first_word = (frame.start_address().as_u64() / 4096) as usize / 64;
+        let range = first_word..first_word + WORDS_PER_HUGE_FRAME;
+        let usable = self.usable.get(range.clone());
+        assert!(
+            usable.is_some_and(|words| words.iter().all(|&word| word == !0)),
+            "deallocating frame {:?} that is not usable RAM",
+            frame
+        );
+        assert!(
+            self.bitmap[range.clone()].iter().all(|&word| word == !0),
+            "deallocating frame {:?} that is not allocated",
+            frame
+        );
+        self.bitmap[range].fill(0);
+        self.used_frames -= 512;
+        self.next = self.next.min(first_word);
+    }
+}
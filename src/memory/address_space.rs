@@ -0,0 +1,203 @@
+//Espaces d'adressage separes. Chaque AddressSpace a sa propre table de niveau 4 ; les entrees utilisees par le noyau (son image,
+//sa pile et la memoire physique mappees par le bootloader dans la moitie basse, et toute la moitie haute geree par le VMM) pointent
+//vers les memes tables de niveau 3 que la table du noyau, le reste de la moitie basse appartient a l'espace d'adressage.
+//Les nouvelles entrees du noyau sont recopiees a chaque activation, pour qu'une region creee entre-temps reste visible.
+
+use super::{cow, vmm::VmError, BitmapFrameAllocator, KernelMemory};
+use x86_64::{
+    registers::control::{Cr3, Cr3Flags},
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, OffsetPageTable, PageTable, PageTableFlags, PhysFrame,
+        Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// Number of level 4 entries in the lower half of the address space.
+const LOWER_HALF_ENTRIES: usize = 256;
+
+/// A level 4 page table of its own, sharing the kernel's mappings.
+///
+/// The user pages and page tables are freed when it is dropped.
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    /// Bit `i` is set if level 4 entry `i` belongs to this address space
+    /// rather than to the kernel.
+    user_entries: [u64; LOWER_HALF_ENTRIES / 64],
+}
+
+/// Returns a reference to the page table stored in `frame`.
+///
+/// This function is unsafe because the caller must guarantee that `frame`
+/// holds a page table and that no other reference to it is in use.
+unsafe fn table_at(offset: VirtAddr, frame: PhysAddr) -> &'static mut PageTable {
+    &mut *(offset + frame.as_u64()).as_mut_ptr::<PageTable>()
+}
+
+/// Returns the frame of the kernel's level 4 table.
+fn kernel_level_4_frame(memory: &mut KernelMemory) -> PhysFrame {
+    let offset = memory.mapper.phys_offset();
+    let table = VirtAddr::from_ptr(memory.mapper.level_4_table());
+    PhysFrame::containing_address(PhysAddr::new((table - offset.as_u64()).as_u64()))
+}
+
+impl AddressSpace {
+    /// Allocates a new level 4 table sharing every entry the kernel uses.
+    pub fn new() -> Result<Self, VmError> {
+        super::with_kernel_memory(|memory| {
+            let frame = FrameAllocator::<Size4KiB>::allocate_frame(&mut memory.frame_allocator)
+                .ok_or(VmError::FrameAllocationFailed)?;
+            let offset = memory.mapper.phys_offset();
+            let table = unsafe { table_at(offset, frame.start_address()) };
+            table.zero();
+
+            let mut user_entries = [0; LOWER_HALF_ENTRIES / 64];
+            for (index, entry) in memory.mapper.level_4_table().iter().enumerate() {
+                if !entry.is_unused() {
+                    table[index] = entry.clone();
+                } else if index < LOWER_HALF_ENTRIES {
+                    user_entries[index / 64] |= 1 << (index % 64);
+                }
+            }
+            Ok(AddressSpace {
+                level_4_frame: frame,
+                user_entries,
+            })
+        })
+        .ok_or(VmError::Uninitialized)?
+    }
+
+    /// Returns the frame holding the level 4 table, as loaded in CR3.
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    /// Returns whether level 4 entry `index` belongs to this address space.
+    pub fn is_user_entry(&self, index: usize) -> bool {
+        index < LOWER_HALF_ENTRIES && self.user_entries[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Returns the first address of the range covered by the user entries,
+    /// i.e. the lowest address that can be mapped in this address space
+    /// without touching the kernel's tables.
+    pub fn first_user_address(&self) -> Option<VirtAddr> {
+        (0..LOWER_HALF_ENTRIES)
+            .find(|&index| self.is_user_entry(index))
+            .map(|index| VirtAddr::new((index as u64) << 39))
+    }
+
+    /// Returns whether this address space is the one loaded in CR3.
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.level_4_frame
+    }
+
+    /// Runs `f` with a mapper for this address space and the kernel's frame
+    /// allocator.
+    ///
+    /// Only pages in the user entries (see `is_user_entry`) should be mapped
+    /// or unmapped, the other entries are shared with the kernel. Returns
+    /// `None` if `init_kernel_memory` was not called yet.
+    pub fn with_mapper<R>(
+        &mut self,
+        f: impl FnOnce(&mut OffsetPageTable, &mut BitmapFrameAllocator) -> R,
+    ) -> Option<R> {
+        let level_4_frame = self.level_4_frame;
+        super::with_kernel_memory(|memory| {
+            let offset = memory.mapper.phys_offset();
+            let table = unsafe { table_at(offset, level_4_frame.start_address()) };
+            let mut mapper = unsafe { OffsetPageTable::new(table, offset) };
+            f(&mut mapper, &mut memory.frame_allocator)
+        })
+    }
+
+    /// Copies the kernel's entries created since `new` and loads this address
+    /// space in CR3.
+    ///
+    /// # Safety
+    ///
+    /// The code running after the switch must not use user mappings of the
+    /// previous address space.
+    pub unsafe fn activate(&self) {
+        super::with_kernel_memory(|memory| {
+            let offset = memory.mapper.phys_offset();
+            let table = table_at(offset, self.level_4_frame.start_address());
+            for (index, entry) in memory.mapper.level_4_table().iter().enumerate() {
+                if !self.is_user_entry(index) {
+                    table[index] = entry.clone();
+                }
+            }
+            Cr3::write(self.level_4_frame, Cr3Flags::empty());
+        })
+        .expect("memory::init_kernel_memory was not called");
+    }
+}
+
+/// Loads the kernel's own level 4 table in CR3.
+pub fn activate_kernel() {
+    super::with_kernel_memory(|memory| {
+        let frame = kernel_level_4_frame(memory);
+        if Cr3::read().0 != frame {
+            unsafe { Cr3::write(frame, Cr3Flags::empty()) };
+        }
+    })
+    .expect("memory::init_kernel_memory was not called");
+}
+
+/// Frees the frames mapped by `table`, a table of the given level, and the
+/// tables below it. The frame of `table` itself is left to the caller.
+///
+/// This function is unsafe because the caller must guarantee that nothing
+/// uses these mappings anymore.
+unsafe fn free_table(offset: VirtAddr, table: &mut PageTable, level: u8, allocator: &mut BitmapFrameAllocator) {
+    for entry in table.iter_mut() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        let addr = entry.addr();
+        if level == 1 {
+            let frame = PhysFrame::<Size4KiB>::containing_address(addr);
+            // une frame partagee en copie a l'ecriture n'est liberee qu'avec son dernier mappage
+            if cow::release_frame(frame) {
+                FrameDeallocator::<Size4KiB>::deallocate_frame(allocator, frame);
+            }
+        } else if flags.contains(PageTableFlags::HUGE_PAGE) {
+            // les pages de 1 GiB ne mappent que de la memoire physique existante, comme dans huge::unmap_range
+            if level == 2 {
+                let frame = PhysFrame::<Size2MiB>::containing_address(addr);
+                FrameDeallocator::<Size2MiB>::deallocate_frame(allocator, frame);
+            }
+        } else {
+            free_table(offset, table_at(offset, addr), level - 1, allocator);
+            let frame = PhysFrame::<Size4KiB>::containing_address(addr);
+            FrameDeallocator::<Size4KiB>::deallocate_frame(allocator, frame);
+        }
+        entry.set_unused();
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        if self.is_active() {
+            activate_kernel();
+        }
+        super::with_kernel_memory(|memory| {
+            let offset = memory.mapper.phys_offset();
+            let allocator = &mut memory.frame_allocator;
+            unsafe {
+                let table = table_at(offset, self.level_4_frame.start_address());
+                for index in (0..LOWER_HALF_ENTRIES).filter(|&index| self.is_user_entry(index)) {
+                    let entry = &mut table[index];
+                    if entry.flags().contains(PageTableFlags::PRESENT) {
+                        free_table(offset, table_at(offset, entry.addr()), 3, allocator);
+                        FrameDeallocator::<Size4KiB>::deallocate_frame(
+                            allocator,
+                            PhysFrame::containing_address(entry.addr()),
+                        );
+                    }
+                }
+                FrameDeallocator::<Size4KiB>::deallocate_frame(allocator, self.level_4_frame);
+            }
+        });
+    }
+}
@@ -0,0 +1,250 @@
+//Un allocateur buddy decoupe la memoire physique en blocs de 2^order frames. Pour une allocation, on prend le plus petit bloc libre
+//assez grand et on le coupe en deux (les deux moities sont des "buddies") jusqu'a obtenir l'ordre demande. A la liberation, un bloc
+//est fusionne avec son buddy tant que celui-ci est libre lui aussi, ce qui reconstitue les grands blocs contigus.
+
+use super::find_usable_region;
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, PhysFrame, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// The largest block order handed out, i.e. blocks of up to `2^MAX_ORDER`
+/// frames (4 MiB).
+pub const MAX_ORDER: usize = 10;
+
+/// The order of a block made of one `Size2MiB` frame.
+const HUGE_PAGE_ORDER: usize = 9;
+
+/// Bit set in `block_orders` for the first frame of an allocated block.
+const ALLOCATED: u8 = 0x80;
+
+/// Header written at the start of every free block.
+///
+/// The list is doubly linked so that a buddy can be unlinked in O(1) when it
+/// gets merged.
+struct FreeBlock {
+    next: Option<usize>,
+    prev: Option<usize>,
+}
+
+/// A physical frame allocator handing out naturally aligned runs of
+/// `2^order` contiguous frames.
+pub struct BuddyFrameAllocator {
+    physical_memory_offset: VirtAddr,
+    /// One list of free block start frames (as frame numbers) per order.
+    free_lists: [Option<usize>; MAX_ORDER + 1],
+    /// For each frame: `order + 1` if a free block of that order starts
+    /// there, `ALLOCATED | (order + 1)` if an allocated block starts there,
+    /// `0` otherwise.
+    block_orders: &'static mut [u8],
+    total_frames: usize,
+    free_frames: usize,
+}
+
+impl BuddyFrameAllocator {
+    /// Create a BuddyFrameAllocator from the usable regions of the passed
+    /// memory map.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the passed memory map is valid, that all
+    /// frames marked as `USABLE` in it are really unused and that the complete
+    /// physical memory is mapped at the passed `physical_memory_offset`.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        let frame_count = usable_regions()
+            .map(|r| r.range.end_frame_number as usize)
+            .max()
+            .unwrap_or(0);
+        let table_frames = frame_count.div_ceil(4096) as u64;
+
+        // la table des ordres occupe le debut de la premiere region assez grande
+        let table_region = find_usable_region(memory_map, table_frames * 4096)
+            .expect("no usable region large enough for the buddy order table");
+        let table_ptr = (physical_memory_offset + table_region.range.start_addr()).as_mut_ptr();
+        let block_orders = core::slice::from_raw_parts_mut(table_ptr, frame_count);
+        block_orders.fill(0);
+
+        let mut allocator = BuddyFrameAllocator {
+            physical_memory_offset,
+            free_lists: [None; MAX_ORDER + 1],
+            block_orders,
+            total_frames: 0,
+            free_frames: 0,
+        };
+
+        let table_start = table_region.range.start_frame_number as usize;
+        let table_end = table_start + table_frames as usize;
+        for region in usable_regions() {
+            let start = region.range.start_frame_number as usize;
+            let end = region.range.end_frame_number as usize;
+            if start == table_start {
+                allocator.add_frames(table_end, end);
+            } else {
+                allocator.add_frames(start, end);
+            }
+        }
+        allocator
+    }
+
+    /// Allocates `2^order` contiguous frames, aligned to their size.
+    ///
+    /// Returns the first frame of the block.
+    pub fn allocate(&mut self, order: usize) -> Option<PhysFrame> {
+        if order > MAX_ORDER {
+            return None;
+        }
+        // find the smallest non-empty list that can satisfy the request
+        let mut current = (order..=MAX_ORDER).find(|&o| self.free_lists[o].is_some())?;
+        let frame = self.free_lists[current].unwrap();
+        unsafe { self.remove(current, frame) };
+
+        // split the block, keeping the lower half and freeing the upper one
+        while current > order {
+            current -= 1;
+            unsafe { self.push(current, frame + (1 << current)) };
+        }
+
+        self.block_orders[frame] = ALLOCATED | (order as u8 + 1);
+        self.free_frames -= 1 << order;
+        Some(Self::frame(frame))
+    }
+
+    /// Frees a block previously returned by `allocate` with the same `order`,
+    /// merging it with its buddies.
+    ///
+    /// Panics if the block is not allocated, e.g. on a double free.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the block is no longer in use.
+    pub unsafe fn deallocate(&mut self, frame: PhysFrame, order: usize) {
+        let mut frame = Self::frame_number(frame);
+        let mut order = order;
+        assert!(frame.is_multiple_of(1 << order), "block is not aligned to its order");
+        assert!(
+            self.block_orders.get(frame) == Some(&(ALLOCATED | (order as u8 + 1))),
+            "deallocating block {:#x} of order {} that is not allocated",
+            frame * 4096,
+            order
+        );
+        self.block_orders[frame] = 0;
+        self.free_frames += 1 << order;
+
+        while order < MAX_ORDER {
+            let buddy = frame ^ (1 << order);
+            if buddy >= self.block_orders.len() || self.block_orders[buddy] as usize != order + 1 {
+                break;
+            }
+            self.remove(order, buddy);
+            frame = frame.min(buddy);
+            order += 1;
+        }
+        self.push(order, frame);
+    }
+
+    /// Returns the number of usable frames managed by the allocator.
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    /// Returns the number of frames that are currently free.
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Returns the number of free blocks of the given order.
+    pub fn free_blocks(&self, order: usize) -> usize {
+        let mut count = 0;
+        let mut current = self.free_lists[order];
+        while let Some(frame) = current {
+            count += 1;
+            current = unsafe { (*self.block(frame)).next };
+        }
+        count
+    }
+
+    /// Adds the frames `start..end` as the largest aligned blocks possible.
+    unsafe fn add_frames(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let mut order = MAX_ORDER;
+            while !start.is_multiple_of(1 << order) || start + (1 << order) > end {
+                order -= 1;
+            }
+            self.push(order, start);
+            self.total_frames += 1 << order;
+            self.free_frames += 1 << order;
+            start += 1 << order;
+        }
+    }
+
+    /// Pushes the free block starting at `frame` to the list of `order`.
+    unsafe fn push(&mut self, order: usize, frame: usize) {
+        let next = self.free_lists[order];
+        if let Some(next) = next {
+            (*self.block(next)).prev = Some(frame);
+        }
+        self.block(frame).write(FreeBlock { next, prev: None });
+        self.free_lists[order] = Some(frame);
+        self.block_orders[frame] = order as u8 + 1;
+    }
+
+    /// Unlinks the free block starting at `frame` from the list of `order`.
+    unsafe fn remove(&mut self, order: usize, frame: usize) {
+        let FreeBlock { next, prev } = self.block(frame).read();
+        match prev {
+            Some(prev) => (*self.block(prev)).next = next,
+            None => self.free_lists[order] = next,
+        }
+        if let Some(next) = next {
+            (*self.block(next)).prev = prev;
+        }
+        self.block_orders[frame] = 0;
+    }
+
+    fn block(&self, frame: usize) -> *mut FreeBlock {
+        (self.physical_memory_offset + frame as u64 * 4096).as_mut_ptr()
+    }
+
+    fn frame(frame_number: usize) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(frame_number as u64 * 4096))
+    }
+
+    fn frame_number(frame: PhysFrame) -> usize {
+        (frame.start_address().as_u64() / 4096) as usize
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate(0)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.deallocate(frame, 0)
+    }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let frame = self.allocate(HUGE_PAGE_ORDER)?;
+        Some(PhysFrame::containing_address(frame.start_address()))
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let frame = PhysFrame::containing_address(frame.start_address());
+        self.deallocate(frame, HUGE_PAGE_ORDER)
+    }
+}
@@ -0,0 +1,318 @@
+//Copie a l'ecriture (copy-on-write). Une frame partagee est mappee en lecture seule dans toutes les tables de pages qui la
+//partagent, avec le bit COW (un bit libre des entrees de table) pour la distinguer d'une page vraiment en lecture seule. Le nombre
+//de mappages de chaque frame partagee est garde dans SHARED_FRAMES ; une frame absente de la table n'a qu'un seul mappage.
+//A la premiere ecriture, le gestionnaire de page fault copie la frame et remappe la page en ecriture ; le dernier mappage restant
+//recupere simplement la frame, sans copie.
+
+use super::BitmapFrameAllocator;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use x86_64::{
+    registers::control::Cr3,
+    structures::{
+        idt::PageFaultErrorCode,
+        paging::{
+            mapper::{MapToError, MappedFrame, TranslateResult, UnmapError},
+            FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+            PageTableFlags, PhysFrame, Size4KiB, Translate,
+        },
+    },
+    VirtAddr,
+};
+
+/// Marks a page that is mapped read-only because its frame is shared.
+pub const COW: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Maximal number of frames shared at the same time.
+pub const MAX_SHARED_FRAMES: usize = 512;
+
+/// An error returned by `share_page`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CowError {
+    /// The source page is not mapped.
+    NotMapped,
+    /// The source page is part of a huge page, only 4 KiB pages can be shared.
+    HugePage,
+    /// The destination page is mapped already.
+    AlreadyMapped,
+    /// `MAX_SHARED_FRAMES` frames are shared already.
+    TooManySharedFrames,
+    /// A page table could not be allocated for the destination page.
+    FrameAllocationFailed,
+}
+
+/// Reference counts of the shared frames, in no particular order.
+struct SharedFrames {
+    entries: [Option<(PhysFrame, usize)>; MAX_SHARED_FRAMES],
+}
+
+impl SharedFrames {
+    const fn new() -> Self {
+        SharedFrames {
+            entries: [None; MAX_SHARED_FRAMES],
+        }
+    }
+
+    fn count(&self, frame: PhysFrame) -> usize {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|(shared, _)| *shared == frame)
+            .map_or(1, |&(_, count)| count)
+    }
+
+    /// Records one more mapping of `frame`.
+    fn increment(&mut self, frame: PhysFrame) -> Result<(), CowError> {
+        if let Some((_, count)) = self.entries.iter_mut().flatten().find(|(shared, _)| *shared == frame) {
+            *count += 1;
+            return Ok(());
+        }
+        let slot = self
+            .entries
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(CowError::TooManySharedFrames)?;
+        *slot = Some((frame, 2));
+        Ok(())
+    }
+
+    /// Records that one mapping of `frame` is gone and returns the number of
+    /// mappings left.
+    fn decrement(&mut self, frame: PhysFrame) -> usize {
+        for slot in self.entries.iter_mut() {
+            if let Some((shared, count)) = slot {
+                if *shared == frame {
+                    *count -= 1;
+                    let left = *count;
+                    // une frame qui n'a plus qu'un mappage n'est plus partagee
+                    if left == 1 {
+                        *slot = None;
+                    }
+                    return left;
+                }
+            }
+        }
+        0
+    }
+}
+
+static SHARED_FRAMES: spin::Mutex<SharedFrames> = spin::Mutex::new(SharedFrames::new());
+static COW_FAULTS: AtomicUsize = AtomicUsize::new(0);
+
+fn with_shared_frames<R>(f: impl FnOnce(&mut SharedFrames) -> R) -> R {
+    x86_64::instructions::interrupts::without_interrupts(|| f(&mut SHARED_FRAMES.lock()))
+}
+
+/// Returns the number of page tables mapping `frame` through `share_page`,
+/// 1 if it is not shared.
+pub fn share_count(frame: PhysFrame) -> usize {
+    with_shared_frames(|shared| shared.count(frame))
+}
+
+/// Returns the number of write faults resolved by copying or reclaiming a
+/// shared frame.
+pub fn cow_faults() -> usize {
+    COW_FAULTS.load(Ordering::Relaxed)
+}
+
+/// Makes `page` copy-on-write in `mapper` and returns its frame and new
+/// flags, to be passed to `map_shared`.
+///
+/// The page becomes read-only with the `COW` flag if it was writable. Until
+/// the frame is mapped a second time, the first write just makes it
+/// writable again.
+pub fn mark_shared<M>(mapper: &mut M, page: Page) -> Result<(PhysFrame, PageTableFlags), CowError>
+where
+    M: Mapper<Size4KiB> + Translate,
+{
+    let (frame, mut flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } => (frame, flags),
+        TranslateResult::Mapped { .. } => return Err(CowError::HugePage),
+        _ => return Err(CowError::NotMapped),
+    };
+    if flags.contains(PageTableFlags::WRITABLE) {
+        flags = (flags - PageTableFlags::WRITABLE) | COW;
+    }
+    // le processeur met a jour ces bits, ils ne se partagent pas
+    flags -= PageTableFlags::ACCESSED | PageTableFlags::DIRTY;
+    // la page est mappee par une page de 4 KiB, la mise a jour ne peut pas echouer
+    unsafe { mapper.update_flags(page, flags).unwrap().flush() };
+    Ok((frame, flags))
+}
+
+/// Maps `page` in `mapper` to a `frame` returned by `mark_shared`, with the
+/// flags returned alongside it.
+///
+/// # Safety
+///
+/// The caller must guarantee that `frame` and `flags` come from `mark_shared`.
+pub unsafe fn map_shared(
+    mapper: &mut impl Mapper<Size4KiB>,
+    page: Page,
+    frame: PhysFrame,
+    flags: PageTableFlags,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), CowError> {
+    with_shared_frames(|shared| shared.increment(frame))?;
+    match mapper.map_to(page, frame, flags, frame_allocator) {
+        Ok(flush) => {
+            flush.flush();
+            Ok(())
+        }
+        Err(err) => {
+            with_shared_frames(|shared| shared.decrement(frame));
+            Err(match err {
+                MapToError::FrameAllocationFailed => CowError::FrameAllocationFailed,
+                MapToError::PageAlreadyMapped(_) | MapToError::ParentEntryHugePage => {
+                    CowError::AlreadyMapped
+                }
+            })
+        }
+    }
+}
+
+/// Maps `dst_page` in `dst` to the frame of `src_page` in `src`, copy-on-write.
+///
+/// Both pages end up read-only with the `COW` flag if the source page was
+/// writable; the first write to either of them gets its own copy of the
+/// frame. To share a page within a single page table, call `mark_shared`
+/// and `map_shared` with the same mapper.
+///
+/// # Safety
+///
+/// The caller must guarantee that the frame is not accessed through another
+/// mapping that would stay writable.
+pub unsafe fn share_page<M>(
+    src: &mut M,
+    src_page: Page,
+    dst: &mut impl Mapper<Size4KiB>,
+    dst_page: Page,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), CowError>
+where
+    M: Mapper<Size4KiB> + Translate,
+{
+    let (frame, flags) = mark_shared(src, src_page)?;
+    map_shared(dst, dst_page, frame, flags, frame_allocator)
+}
+
+/// Unmaps a page that may be shared, and gives its frame back to
+/// `frame_deallocator` once no other page table maps it.
+///
+/// # Safety
+///
+/// The caller must guarantee that the page is not used anymore.
+pub unsafe fn unmap_page(
+    mapper: &mut impl Mapper<Size4KiB>,
+    page: Page,
+    frame_deallocator: &mut impl FrameDeallocator<Size4KiB>,
+) -> Result<(), UnmapError> {
+    let (frame, flush) = mapper.unmap(page)?;
+    flush.flush();
+    if release_frame(frame) {
+        frame_deallocator.deallocate_frame(frame);
+    }
+    Ok(())
+}
+
+/// Records that one mapping of `frame` is gone, and returns whether it was
+/// the last one, i.e. whether the frame must be deallocated.
+pub(super) fn release_frame(frame: PhysFrame) -> bool {
+    with_shared_frames(|shared| shared.decrement(frame)) == 0
+}
+
+/// Gives the faulting page its own writable frame, copying the shared one
+/// unless no other page table maps it anymore.
+fn resolve_write(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut BitmapFrameAllocator,
+    addr: VirtAddr,
+) -> bool {
+    let page = Page::<Size4KiB>::containing_address(addr);
+    let (frame, flags) = match mapper.translate(addr) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } if flags.contains(COW) => (frame, flags),
+        _ => return false,
+    };
+    let mut shared = match SHARED_FRAMES.try_lock() {
+        Some(shared) => shared,
+        None => return false,
+    };
+    let writable = (flags - COW) | PageTableFlags::WRITABLE;
+
+    if shared.count(frame) == 1 {
+        // les autres mappages ont disparu : la frame est de nouveau a nous seuls
+        return match unsafe { mapper.update_flags(page, writable) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(_) => false,
+        };
+    }
+
+    let copy = match FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator) {
+        Some(copy) => copy,
+        None => return false,
+    };
+    let offset = mapper.phys_offset();
+    unsafe {
+        ptr::copy_nonoverlapping(
+            (offset + frame.start_address().as_u64()).as_ptr::<u8>(),
+            (offset + copy.start_address().as_u64()).as_mut_ptr::<u8>(),
+            Size4KiB::SIZE as usize,
+        );
+        // l'entree existe deja : remplacer la frame ne demande pas de nouvelle table
+        mapper.unmap(page).unwrap().1.ignore();
+        mapper
+            .map_to(page, copy, writable, frame_allocator)
+            .unwrap()
+            .flush();
+    }
+    shared.decrement(frame);
+    true
+}
+
+/// Resolves a write fault on a copy-on-write page of the active page table.
+///
+/// Called by the page fault handler. Returns `false` if the fault was not
+/// caused by a write to a `COW` page, or if the locks are held.
+pub fn resolve_page_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    let write_to_present = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+    if !error_code.contains(write_to_present) || error_code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+        return false;
+    }
+
+    let mut kernel_memory = match super::KERNEL_MEMORY.try_lock() {
+        Some(kernel_memory) => kernel_memory,
+        None => return false,
+    };
+    let memory = match kernel_memory.as_mut() {
+        Some(memory) => memory,
+        None => return false,
+    };
+
+    //la faute concerne la table active, qui n'est pas forcement celle du noyau ; le verrou de KERNEL_MEMORY serialise
+    //toutes les modifications des tables de pages
+    let offset = memory.mapper.phys_offset();
+    let active_table = Cr3::read().0.start_address();
+    let kernel_table = VirtAddr::from_ptr(memory.mapper.level_4_table()) - offset.as_u64();
+    let resolved = if active_table.as_u64() == kernel_table.as_u64() {
+        resolve_write(&mut memory.mapper, &mut memory.frame_allocator, addr)
+    } else {
+        let table = unsafe { &mut *(offset + active_table.as_u64()).as_mut_ptr::<PageTable>() };
+        let mut mapper = unsafe { OffsetPageTable::new(table, offset) };
+        resolve_write(&mut mapper, &mut memory.frame_allocator, addr)
+    };
+    if resolved {
+        COW_FAULTS.fetch_add(1, Ordering::Relaxed);
+    }
+    resolved
+}
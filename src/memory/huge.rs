@@ -0,0 +1,216 @@
+//Pages de 2 MiB et 1 GiB. Une grande page est mappee directement par une entree de niveau 2 (ou 3), sans table de niveau 1 : une
+//seule entree du TLB couvre alors 2 MiB, et la table de 4 KiB qui aurait decrit ces 512 pages n'est pas allouee. Les fonctions de
+//ce module mappent une plage avec les plus grandes pages possibles selon l'alignement, et retombent sur des pages de 4 KiB sinon.
+
+use super::vmm::VmError;
+use core::arch::x86_64::__cpuid;
+use x86_64::{
+    structures::paging::{
+        mapper::{MapToError, TranslateError, UnmapError},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize,
+        PageTableFlags, PhysFrame, Size1GiB, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// Returns whether the CPU supports 1 GiB pages.
+pub fn supports_1gib_pages() -> bool {
+    // CPUID 0x8000_0001 : le bit 26 de EDX (Page1GB) indique le support des pages de 1 GiB
+    let max_extended_leaf = __cpuid(0x8000_0000).eax;
+    max_extended_leaf >= 0x8000_0001 && __cpuid(0x8000_0001).edx & (1 << 26) != 0
+}
+
+/// Returns whether a page of size `S` can map `virt` to `phys` without going
+/// past `remaining` bytes.
+fn fits<S: PageSize>(virt: VirtAddr, phys: PhysAddr, remaining: u64) -> bool {
+    virt.is_aligned(S::SIZE) && phys.is_aligned(S::SIZE) && remaining >= S::SIZE
+}
+
+unsafe fn map_page<S: PageSize, M: Mapper<S>>(
+    mapper: &mut M,
+    virt: VirtAddr,
+    phys: PhysAddr,
+    flags: PageTableFlags,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<S>> {
+    let page = Page::<S>::containing_address(virt);
+    let frame = PhysFrame::<S>::containing_address(phys);
+    mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+    Ok(())
+}
+
+/// Maps `size` bytes at `virt` to the physical range starting at `phys`,
+/// using 1 GiB and 2 MiB pages wherever both addresses are aligned.
+///
+/// # Safety
+///
+/// The caller must guarantee that the physical range may be accessed with the
+/// given flags.
+pub unsafe fn map_physical_range(
+    mapper: &mut OffsetPageTable,
+    virt: VirtAddr,
+    phys: PhysAddr,
+    size: u64,
+    flags: PageTableFlags,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), VmError> {
+    let use_1gib_pages = supports_1gib_pages();
+    let mut offset = 0;
+    while offset < size {
+        let (virt, phys, remaining) = (virt + offset, phys + offset, size - offset);
+        offset += if use_1gib_pages && fits::<Size1GiB>(virt, phys, remaining) {
+            map_page::<Size1GiB, _>(mapper, virt, phys, flags, frame_allocator)?;
+            Size1GiB::SIZE
+        } else if fits::<Size2MiB>(virt, phys, remaining) {
+            map_page::<Size2MiB, _>(mapper, virt, phys, flags, frame_allocator)?;
+            Size2MiB::SIZE
+        } else {
+            map_page::<Size4KiB, _>(mapper, virt, phys, flags, frame_allocator)?;
+            Size4KiB::SIZE
+        };
+    }
+    Ok(())
+}
+
+/// Error of `map_range`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapRangeError {
+    pub error: VmError,
+    /// Number of bytes at the start of the range that were mapped (or were
+    /// mapped already) before the error.
+    pub mapped: u64,
+}
+
+/// Maps `size` bytes at `virt` to newly allocated frames, using 2 MiB pages
+/// for the aligned parts of the range when a `Size2MiB` frame is available.
+///
+/// Pages of the range that are mapped already are left alone. On error, the
+/// pages mapped so far stay mapped and the error tells how far it got.
+pub fn map_range<A>(
+    mapper: &mut OffsetPageTable,
+    virt: VirtAddr,
+    size: u64,
+    flags: PageTableFlags,
+    frame_allocator: &mut A,
+) -> Result<(), MapRangeError>
+where
+    A: FrameAllocator<Size4KiB>
+        + FrameAllocator<Size2MiB>
+        + FrameDeallocator<Size4KiB>
+        + FrameDeallocator<Size2MiB>,
+{
+    let mut addr = virt;
+    map_range_from(mapper, &mut addr, virt + size, flags, frame_allocator)
+        .map_err(|error| MapRangeError { error, mapped: addr - virt })
+}
+
+/// Maps the pages from `addr` to `end`, advancing `addr` past every page
+/// that is mapped.
+fn map_range_from<A>(
+    mapper: &mut OffsetPageTable,
+    addr: &mut VirtAddr,
+    end: VirtAddr,
+    flags: PageTableFlags,
+    frame_allocator: &mut A,
+) -> Result<(), VmError>
+where
+    A: FrameAllocator<Size4KiB>
+        + FrameAllocator<Size2MiB>
+        + FrameDeallocator<Size4KiB>
+        + FrameDeallocator<Size2MiB>,
+{
+    while *addr < end {
+        if addr.is_aligned(Size2MiB::SIZE) && end - *addr >= Size2MiB::SIZE {
+            if let Some(frame) = FrameAllocator::<Size2MiB>::allocate_frame(frame_allocator) {
+                let result = unsafe {
+                    map_page::<Size2MiB, _>(mapper, *addr, frame.start_address(), flags, frame_allocator)
+                };
+                match result {
+                    Ok(()) => {
+                        *addr += Size2MiB::SIZE;
+                        continue;
+                    }
+                    // une partie de la plage est deja mappee en pages de 4 KiB : on complete page par page
+                    Err(MapToError::PageAlreadyMapped(_)) | Err(MapToError::ParentEntryHugePage) => unsafe {
+                        FrameDeallocator::<Size2MiB>::deallocate_frame(frame_allocator, frame)
+                    },
+                    Err(err) => {
+                        unsafe { FrameDeallocator::<Size2MiB>::deallocate_frame(frame_allocator, frame) };
+                        return Err(err.into());
+                    }
+                }
+            }
+        }
+
+        let page = Page::<Size4KiB>::containing_address(*addr);
+        if let Err(TranslateError::PageNotMapped) = Mapper::<Size4KiB>::translate_page(mapper, page) {
+            let frame = FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator)
+                .ok_or(VmError::FrameAllocationFailed)?;
+            let result = unsafe {
+                map_page::<Size4KiB, _>(mapper, *addr, frame.start_address(), flags, frame_allocator)
+            };
+            if let Err(err) = result {
+                unsafe { FrameDeallocator::<Size4KiB>::deallocate_frame(frame_allocator, frame) };
+                return Err(err.into());
+            }
+        }
+        *addr += Size4KiB::SIZE;
+    }
+    Ok(())
+}
+
+/// Unmaps every mapped page between `virt` and `virt + size`, whatever its
+/// size, and gives the frames to `frame_deallocator` if there is one.
+///
+/// Huge pages are unmapped as a whole, the range should therefore not start
+/// or end in the middle of one. The frames of 1 GiB pages are never
+/// deallocated, such pages only map physical ranges.
+///
+/// # Safety
+///
+/// The caller must guarantee that the range is not used anymore.
+pub unsafe fn unmap_range<D>(
+    mapper: &mut OffsetPageTable,
+    virt: VirtAddr,
+    size: u64,
+    mut frame_deallocator: Option<&mut D>,
+) where
+    D: FrameDeallocator<Size4KiB> + FrameDeallocator<Size2MiB>,
+{
+    let end = virt + size;
+    let mut addr = virt;
+    while addr < end {
+        let page = Page::<Size4KiB>::containing_address(addr);
+        match Mapper::<Size4KiB>::unmap(mapper, page) {
+            Ok((frame, flush)) => {
+                flush.flush();
+                if let Some(deallocator) = frame_deallocator.as_mut() {
+                    FrameDeallocator::<Size4KiB>::deallocate_frame(*deallocator, frame);
+                }
+                addr += Size4KiB::SIZE;
+            }
+            Err(UnmapError::PageNotMapped) => addr += Size4KiB::SIZE,
+            Err(UnmapError::ParentEntryHugePage) => {
+                let page = Page::<Size2MiB>::containing_address(addr);
+                match Mapper::<Size2MiB>::unmap(mapper, page) {
+                    Ok((frame, flush)) => {
+                        flush.flush();
+                        if let Some(deallocator) = frame_deallocator.as_mut() {
+                            FrameDeallocator::<Size2MiB>::deallocate_frame(*deallocator, frame);
+                        }
+                        addr = page.start_address() + Size2MiB::SIZE;
+                    }
+                    Err(UnmapError::ParentEntryHugePage) => {
+                        let page = Page::<Size1GiB>::containing_address(addr);
+                        let (_, flush) = Mapper::<Size1GiB>::unmap(mapper, page)
+                            .unwrap_or_else(|err| panic!("failed to unmap {:?}: {:?}", page, err));
+                        flush.flush();
+                        addr = page.start_address() + Size1GiB::SIZE;
+                    }
+                    Err(err) => panic!("failed to unmap {:?}: {:?}", page, err),
+                }
+            }
+            Err(err) => panic!("failed to unmap {:?}: {:?}", page, err),
+        }
+    }
+}
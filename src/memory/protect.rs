@@ -0,0 +1,226 @@
+//Protection W^X des mappages du noyau. Une page est soit inscriptible, soit executable, jamais les deux : le code (.text) est en
+//lecture et execution, les donnees en lecture seule (.rodata) ou en lecture et ecriture (.data, .bss) sans execution. Les droits de
+//chaque segment sont lus dans les en-tetes de programme du fichier ELF du noyau, que le bootloader laisse en memoire dans la region
+//Kernel de la carte memoire. Les autres mappages du noyau ne sont pas executables non plus : la memoire physique mappee par le
+//bootloader (qui contient un alias de .text et des donnees) et la pile de demarrage.
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::{
+    instructions::tlb,
+    registers::{
+        control::{Cr0, Cr0Flags},
+        model_specific::{Efer, EferFlags},
+    },
+    structures::paging::{
+        mapper::FlagUpdateError, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+        Size1GiB, Size4KiB, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// ELF program header type of a loadable segment.
+const PT_LOAD: u32 = 1;
+/// ELF segment permission bits.
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+/// Maximal number of loadable segments taken into account.
+const MAX_SEGMENTS: usize = 16;
+
+/// An error that occurred while remapping the kernel image.
+#[derive(Debug)]
+pub enum ProtectError {
+    /// The memory map has no `Kernel` region.
+    NoKernelImage,
+    /// The kernel region does not start with a valid 64-bit ELF header.
+    InvalidElf,
+    /// The kernel has more than `MAX_SEGMENTS` loadable segments.
+    TooManySegments,
+    /// A page of a segment is not mapped with a 4 KiB page.
+    FlagUpdate(VirtAddr, FlagUpdateError),
+}
+
+/// Enables no-execute pages (EFER.NXE) and makes the kernel honor read-only
+/// pages (CR0.WP).
+///
+/// Must be called before any page is mapped with `NO_EXECUTE`, the bit is
+/// reserved otherwise.
+pub fn enable_nx() {
+    unsafe {
+        Efer::update(|flags| *flags |= EferFlags::NO_EXECUTE_ENABLE);
+        Cr0::update(|flags| *flags |= Cr0Flags::WRITE_PROTECT);
+    }
+}
+
+/// A loadable segment of the kernel image.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: VirtAddr,
+    end: VirtAddr,
+    flags: u32,
+}
+
+impl Segment {
+    const EMPTY: Segment = Segment {
+        start: VirtAddr::zero(),
+        end: VirtAddr::zero(),
+        flags: 0,
+    };
+
+    fn contains(&self, page: Page) -> bool {
+        // une page partagee par deux segments fait partie des deux
+        page.start_address() < self.end && self.start < page.start_address() + page.size()
+    }
+}
+
+/// Reads a little endian integer of `N` bytes at `offset` in `image`.
+fn read<const N: usize>(image: &[u8], offset: usize) -> Result<u64, ProtectError> {
+    let bytes = image.get(offset..offset + N).ok_or(ProtectError::InvalidElf)?;
+    Ok(bytes.iter().rev().fold(0, |value, &byte| value << 8 | u64::from(byte)))
+}
+
+/// Reads the loadable segments from the program headers of the ELF `image`.
+fn parse_segments(image: &[u8]) -> Result<([Segment; MAX_SEGMENTS], usize), ProtectError> {
+    // ELFCLASS64, little endian
+    if image.get(..6) != Some(b"\x7fELF\x02\x01".as_slice()) {
+        return Err(ProtectError::InvalidElf);
+    }
+    let phoff = read::<8>(image, 0x20)? as usize;
+    let phentsize = read::<2>(image, 0x36)? as usize;
+    let phnum = read::<2>(image, 0x38)? as usize;
+
+    let mut segments = [Segment::EMPTY; MAX_SEGMENTS];
+    let mut count = 0;
+    for index in 0..phnum {
+        let header = phoff + index * phentsize;
+        if read::<4>(image, header)? as u32 != PT_LOAD {
+            continue;
+        }
+        let vaddr = read::<8>(image, header + 0x10)?;
+        let memsz = read::<8>(image, header + 0x28)?;
+        if memsz == 0 {
+            continue;
+        }
+        let segment = segments.get_mut(count).ok_or(ProtectError::TooManySegments)?;
+        *segment = Segment {
+            start: VirtAddr::try_new(vaddr).map_err(|_| ProtectError::InvalidElf)?,
+            end: VirtAddr::try_new(vaddr + memsz).map_err(|_| ProtectError::InvalidElf)?,
+            flags: read::<4>(image, header + 4)? as u32,
+        };
+        count += 1;
+    }
+    Ok((segments, count))
+}
+
+/// Remaps every page of the kernel image according to the permissions of
+/// its ELF segment: code is read-only and executable, everything else is
+/// no-execute, and only writable segments stay writable.
+///
+/// A page shared by two segments gets the union of their permissions. The
+/// physical memory mapping and the boot stack are made no-execute too.
+///
+/// # Safety
+///
+/// The caller must guarantee that the memory map comes from the bootloader,
+/// that `enable_nx` was called and that the current stack is the boot stack.
+pub unsafe fn remap_kernel(
+    mapper: &mut OffsetPageTable,
+    memory_map: &MemoryMap,
+) -> Result<(), ProtectError> {
+    let kernel = memory_map
+        .iter()
+        .filter(|r| r.region_type == MemoryRegionType::Kernel)
+        .min_by_key(|r| r.range.start_addr())
+        .ok_or(ProtectError::NoKernelImage)?;
+    let image_start = mapper.phys_offset() + kernel.range.start_addr();
+    let image_size = kernel.range.end_addr() - kernel.range.start_addr();
+    let image = core::slice::from_raw_parts(image_start.as_ptr::<u8>(), image_size as usize);
+    let (segments, count) = parse_segments(image)?;
+    let segments = &segments[..count];
+
+    for segment in segments {
+        let first = Page::<Size4KiB>::containing_address(segment.start);
+        let last = Page::<Size4KiB>::containing_address(segment.end - 1u64);
+        for page in Page::range_inclusive(first, last) {
+            let permissions = segments
+                .iter()
+                .filter(|other| other.contains(page))
+                .fold(0, |permissions, other| permissions | other.flags);
+            let mut flags = PageTableFlags::PRESENT;
+            if permissions & PF_W != 0 {
+                flags |= PageTableFlags::WRITABLE;
+            }
+            if permissions & PF_X == 0 {
+                flags |= PageTableFlags::NO_EXECUTE;
+            }
+            Mapper::<Size4KiB>::update_flags(mapper, page, flags)
+                .map_err(|err| ProtectError::FlagUpdate(page.start_address(), err))?
+                .flush();
+        }
+    }
+
+    protect_physical_memory(mapper, memory_map);
+    protect_boot_stack(mapper, memory_map)
+}
+
+/// Sets `NO_EXECUTE` on the mapping of the complete physical memory.
+///
+/// The bootloader maps it with 2 MiB pages in level 4 entries of its own, so
+/// the bit is set in the level 3 entries covering it.
+unsafe fn protect_physical_memory(mapper: &mut OffsetPageTable, memory_map: &MemoryMap) {
+    let offset = mapper.phys_offset();
+    let phys_end = memory_map.iter().map(|r| r.range.end_addr()).max().unwrap_or(0);
+    if phys_end == 0 {
+        return;
+    }
+    let first = Page::<Size1GiB>::containing_address(offset);
+    let last = Page::<Size1GiB>::containing_address(offset + (phys_end - 1));
+    for page in Page::range_inclusive(first, last) {
+        let level_4_entry = &mapper.level_4_table()[page.p4_index()];
+        if level_4_entry.is_unused() {
+            continue;
+        }
+        let level_3_table = &mut *(offset + level_4_entry.addr().as_u64()).as_mut_ptr::<PageTable>();
+        let entry = &mut level_3_table[page.p3_index()];
+        if !entry.is_unused() {
+            entry.set_flags(entry.flags() | PageTableFlags::NO_EXECUTE);
+        }
+    }
+    tlb::flush_all();
+}
+
+/// Sets `NO_EXECUTE` on the pages of the boot stack, i.e. the pages around
+/// the current stack pointer that are backed by the `KernelStack` region.
+unsafe fn protect_boot_stack(
+    mapper: &mut OffsetPageTable,
+    memory_map: &MemoryMap,
+) -> Result<(), ProtectError> {
+    let in_stack = |phys: PhysAddr| {
+        memory_map.iter().any(|r| {
+            r.region_type == MemoryRegionType::KernelStack
+                && phys.as_u64() >= r.range.start_addr()
+                && phys.as_u64() < r.range.end_addr()
+        })
+    };
+    let stack_pointer: u64;
+    core::arch::asm!("mov {}, rsp", out(reg) stack_pointer, options(nomem, nostack));
+    let current = Page::<Size4KiB>::containing_address(VirtAddr::new(stack_pointer));
+
+    // la pile s'etend des deux cotes de rsp ; la page de garde, non mappee, arrete la descente
+    let mut bottom = current;
+    while let Some(below) = bottom.start_address().as_u64().checked_sub(4096) {
+        let below = Page::containing_address(VirtAddr::new(below));
+        match mapper.translate_addr(below.start_address()) {
+            Some(phys) if in_stack(phys) => bottom = below,
+            _ => break,
+        }
+    }
+    let mut page = bottom;
+    while mapper.translate_addr(page.start_address()).is_some_and(in_stack) {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        Mapper::<Size4KiB>::update_flags(mapper, page, flags)
+            .map_err(|err| ProtectError::FlagUpdate(page.start_address(), err))?
+            .flush();
+        page += 1;
+    }
+    Ok(())
+}
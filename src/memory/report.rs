@@ -0,0 +1,144 @@
+//Bilan de la memoire physique d'apres la carte memoire du bootloader : pour chaque type de region, le nombre de regions, leur taille
+//totale et la plage d'adresses qu'elles couvrent. Les types inconnus (UnknownUefi, UnknownBios) sont regroupes dans une seule ligne.
+
+use super::BitmapFrameAllocator;
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::fmt;
+
+/// The region types listed in a report with their names, in display order.
+const KINDS: [(MemoryRegionType, &str); 14] = [
+    (MemoryRegionType::Usable, "Usable"),
+    (MemoryRegionType::InUse, "InUse"),
+    (MemoryRegionType::Reserved, "Reserved"),
+    (MemoryRegionType::AcpiReclaimable, "AcpiReclaimable"),
+    (MemoryRegionType::AcpiNvs, "AcpiNvs"),
+    (MemoryRegionType::BadMemory, "BadMemory"),
+    (MemoryRegionType::Kernel, "Kernel"),
+    (MemoryRegionType::KernelStack, "KernelStack"),
+    (MemoryRegionType::PageTable, "PageTable"),
+    (MemoryRegionType::Bootloader, "Bootloader"),
+    (MemoryRegionType::FrameZero, "FrameZero"),
+    (MemoryRegionType::Empty, "Empty"),
+    (MemoryRegionType::BootInfo, "BootInfo"),
+    (MemoryRegionType::Package, "Package"),
+];
+
+//Les regions Bootloader ne sont pas rendues : le bootloader 0.9 y laisse les tables de pages actives, le BootInfo et la pile
+//de demarrage, qui servent encore. Les tables ACPI, elles, sont copiees dans acpi::TABLES par acpi::init.
+
+/// Region types whose frames can be given to the frame allocator once the
+/// kernel does not need their content anymore.
+pub const RECLAIMABLE: [MemoryRegionType; 1] = [MemoryRegionType::AcpiReclaimable];
+
+/// The regions of one type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionSummary {
+    pub regions: usize,
+    pub bytes: u64,
+    /// The lowest start and highest end address of these regions.
+    pub lowest: u64,
+    pub highest: u64,
+}
+
+impl RegionSummary {
+    const EMPTY: RegionSummary = RegionSummary {
+        regions: 0,
+        bytes: 0,
+        lowest: u64::MAX,
+        highest: 0,
+    };
+}
+
+/// A summary of the bootloader's memory map by region type.
+pub struct MemoryReport {
+    //une entree par type de KINDS, plus une derniere pour les types inconnus
+    summaries: [RegionSummary; KINDS.len() + 1],
+}
+
+/// Returns the index of the summary of `kind` in `MemoryReport::summaries`.
+fn index_of(kind: MemoryRegionType) -> usize {
+    KINDS.iter().position(|&(known, _)| known == kind).unwrap_or(KINDS.len())
+}
+
+impl MemoryReport {
+    pub fn new(memory_map: &MemoryMap) -> Self {
+        let mut summaries = [RegionSummary::EMPTY; KINDS.len() + 1];
+        for region in memory_map.iter() {
+            let (start, end) = (region.range.start_addr(), region.range.end_addr());
+            let summary = &mut summaries[index_of(region.region_type)];
+            summary.regions += 1;
+            summary.bytes += end - start;
+            summary.lowest = summary.lowest.min(start);
+            summary.highest = summary.highest.max(end);
+        }
+        MemoryReport { summaries }
+    }
+
+    /// Returns the summary of the regions of type `kind`. All unknown types
+    /// share one summary.
+    pub fn summary(&self, kind: MemoryRegionType) -> RegionSummary {
+        self.summaries[index_of(kind)]
+    }
+
+    /// Returns the number of bytes in regions of type `kind`.
+    pub fn bytes(&self, kind: MemoryRegionType) -> u64 {
+        self.summary(kind).bytes
+    }
+
+    /// Returns the number of bytes described by the memory map.
+    pub fn total_bytes(&self) -> u64 {
+        self.summaries.iter().map(|summary| summary.bytes).sum()
+    }
+
+    /// Returns the number of bytes the frame allocator can use.
+    pub fn usable_bytes(&self) -> u64 {
+        self.bytes(MemoryRegionType::Usable)
+    }
+
+    /// Returns the number of bytes in `RECLAIMABLE` regions.
+    pub fn reclaimable_bytes(&self) -> u64 {
+        RECLAIMABLE.iter().map(|&kind| self.bytes(kind)).sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "physical memory:")?;
+        let names = KINDS.iter().map(|&(_, name)| name).chain(["Unknown"]);
+        for (name, summary) in names.zip(self.summaries.iter()) {
+            if summary.regions == 0 {
+                continue;
+            }
+            writeln!(
+                f,
+                "  {:<16} {:>8} KiB in {:>2} region(s), {:#012x}-{:#012x}",
+                name,
+                summary.bytes / 1024,
+                summary.regions,
+                summary.lowest,
+                summary.highest,
+            )?;
+        }
+        write!(
+            f,
+            "  total {} KiB, usable {} KiB, reclaimable {} KiB",
+            self.total_bytes() / 1024,
+            self.usable_bytes() / 1024,
+            self.reclaimable_bytes() / 1024,
+        )
+    }
+}
+
+/// Gives the frames of the `RECLAIMABLE` regions to `frame_allocator` and
+/// returns how many frames were added.
+///
+/// # Safety
+///
+/// The caller must guarantee that it is called once, after the ACPI tables
+/// were read (`acpi::init`).
+pub unsafe fn reclaim_boot_memory(memory_map: &MemoryMap, frame_allocator: &mut BitmapFrameAllocator) -> usize {
+    RECLAIMABLE
+        .iter()
+        .map(|&kind| frame_allocator.reclaim(memory_map, kind))
+        .sum()
+}
@@ -0,0 +1,141 @@
+//Piles du noyau avec page de garde. Chaque pile occupe sa propre region Stack du gestionnaire d'adresses virtuelles, dont la page
+//la plus basse n'est jamais mappee : un debordement ecrit dans cette page et provoque une page fault au lieu d'ecraser silencieusement
+//la memoire situee en dessous. Les piles sont enregistrees avec leur nom pour que le gestionnaire de faute puisse dire laquelle a deborde.
+
+use super::vmm::{self, RegionKind, VmError, MAX_REGIONS};
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+/// Default size of a kernel stack, without its guard page.
+pub const DEFAULT_STACK_SIZE: u64 = 8 * 4096;
+
+/// A registered stack: its region start (the guard page) and its name.
+#[derive(Clone, Copy)]
+struct StackInfo {
+    region_start: VirtAddr,
+    name: &'static str,
+}
+
+//il ne peut pas y avoir plus de piles que de regions
+static STACKS: spin::Mutex<[Option<StackInfo>; MAX_REGIONS]> = spin::Mutex::new([None; MAX_REGIONS]);
+
+/// A mapped kernel stack with an unmapped guard page below it.
+///
+/// The stack is unmapped and its frames are freed when it is dropped.
+pub struct KernelStack {
+    region_start: VirtAddr,
+    bottom: VirtAddr,
+    top: VirtAddr,
+    name: &'static str,
+}
+
+impl KernelStack {
+    /// Maps a stack of `size` bytes (rounded up to whole pages) in a new
+    /// `Stack` region.
+    ///
+    /// Fails with `VmError::TooManyRegions` if `MAX_REGIONS` stacks exist
+    /// already.
+    pub fn new(size: u64, name: &'static str) -> Result<Self, VmError> {
+        let flags =
+            PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        let region = super::with_kernel_memory(|memory| {
+            vmm::with_vmm(|vmm| {
+                let guard_size = vmm::STACK_GUARD_PAGES * 4096;
+                let region = vmm.allocate(size + guard_size, RegionKind::Stack, flags)?;
+                vmm.map_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                    .inspect_err(|_| {
+                        // map_region a deja defait les mappages partiels
+                        vmm.release(region.start).unwrap();
+                    })
+            })
+        })
+        .ok_or(VmError::Uninitialized)??;
+
+        let stack = KernelStack {
+            region_start: region.start,
+            bottom: region.start + region.guard_size(),
+            top: region.end(),
+            name,
+        };
+        let info = StackInfo {
+            region_start: region.start,
+            name,
+        };
+        let registered = x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut stacks = STACKS.lock();
+            let slot = stacks.iter_mut().find(|slot| slot.is_none())?;
+            *slot = Some(info);
+            Some(())
+        });
+        // sans place dans STACKS, drop rend la region et ses frames
+        registered.ok_or(VmError::TooManyRegions)?;
+        Ok(stack)
+    }
+
+    /// Returns the initial stack pointer, i.e. the end of the stack.
+    pub fn top(&self) -> VirtAddr {
+        self.top
+    }
+
+    /// Returns the lowest usable address of the stack.
+    pub fn bottom(&self) -> VirtAddr {
+        self.bottom
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut stacks = STACKS.lock();
+            for slot in stacks.iter_mut() {
+                if slot.is_some_and(|info| info.region_start == self.region_start) {
+                    *slot = None;
+                }
+            }
+        });
+        super::with_kernel_memory(|memory| {
+            vmm::with_vmm(|vmm| {
+                unsafe {
+                    vmm.unmap_region(self.region_start, &mut memory.mapper, &mut memory.frame_allocator)
+                        .unwrap()
+                };
+                vmm.release(self.region_start).unwrap();
+            })
+        });
+    }
+}
+
+/// Returns the name of the stack whose guard page contains `addr`.
+///
+/// Meant for fault handlers: returns `None` instead of blocking when the
+/// locks are held.
+pub fn overflowed_stack(addr: VirtAddr) -> Option<&'static str> {
+    let region = vmm::try_with_vmm(|vmm| vmm.find(addr).copied())??;
+    if !region.in_guard(addr) {
+        return None;
+    }
+    let stacks = STACKS.try_lock()?;
+    stacks
+        .iter()
+        .flatten()
+        .find(|info| info.region_start == region.start)
+        .map(|info| info.name)
+}
+
+/// Replaces the static interrupt stack set up by `gdt::init` with a guarded
+/// kernel stack, so that an overflow in the double fault handler is detected
+/// too.
+///
+/// Must be called after `init_kernel_memory`.
+pub fn init_interrupt_stacks() -> Result<(), VmError> {
+    use crate::gdt;
+
+    let stack = KernelStack::new(DEFAULT_STACK_SIZE, "double fault")?;
+    unsafe { gdt::set_interrupt_stack(gdt::DOUBLE_FAULT_IST_INDEX, stack.top()) };
+    // cette pile sert jusqu'a l'arret du noyau
+    core::mem::forget(stack);
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//Copies entre le noyau et la memoire utilisateur. Chaque page de la plage utilisateur est verifiee dans les tables de pages avant
+//la copie (presente, accessible en mode utilisateur, inscriptible pour une ecriture), puis la copie est faite dans une fenetre
+//with_user_access pour que SMAP la laisse passer.
+//La verification et la copie sont deux etapes separees, sans verrou sur les tables de pages utilisateur entre les deux : si une
+//autre tache demappe ou rend en lecture seule une page de la plage entre temps, la copie provoque une page fault au lieu d'une
+//erreur. C'est a l'appelant de garantir que l'espace d'adressage ne change pas pendant la copie (un seul processeur, pas de
+//preemption pendant l'appel).
+
+use super::walk;
+use crate::cpu::with_user_access;
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+/// First address after the lower half of the address space, which holds the
+/// user mappings.
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// An error returned by `copy_from_user` and `copy_to_user`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserCopyError {
+    /// The range is not entirely in the user half of the address space.
+    BadAddress(VirtAddr),
+    /// The page containing the address is not mapped as a user page (or not
+    /// writable, for `copy_to_user`).
+    NotAccessible(VirtAddr),
+}
+
+/// Checks that every page of the `size` bytes at `addr` is accessible from
+/// user mode, and writable too if `write` is set.
+fn check_user_range(addr: VirtAddr, size: usize, write: bool) -> Result<(), UserCopyError> {
+    let end = addr
+        .as_u64()
+        .checked_add(size as u64)
+        .filter(|&end| end <= USER_SPACE_END)
+        .ok_or(UserCopyError::BadAddress(addr))?;
+    let mut required = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if write {
+        required |= PageTableFlags::WRITABLE;
+    }
+
+    let mut page = addr.align_down(4096u64);
+    while page.as_u64() < end {
+        // tous les niveaux de la table doivent autoriser l'acces, pas seulement l'entree finale
+        match walk::effective_flags(page) {
+            Some(flags) if flags.contains(required) => {}
+            _ => return Err(UserCopyError::NotAccessible(page.max(addr))),
+        }
+        page += 4096u64;
+    }
+    Ok(())
+}
+
+/// Copies `dst.len()` bytes from the user address `src` into `dst`.
+///
+/// The mappings of the range must not change during the call: they are
+/// checked before the copy, not while it runs.
+pub fn copy_from_user(dst: &mut [u8], src: VirtAddr) -> Result<(), UserCopyError> {
+    check_user_range(src, dst.len(), false)?;
+    with_user_access(|| unsafe {
+        core::ptr::copy_nonoverlapping(src.as_ptr::<u8>(), dst.as_mut_ptr(), dst.len())
+    });
+    Ok(())
+}
+
+/// Copies `src` to the user address `dst`.
+///
+/// The mappings of the range must not change during the call: they are
+/// checked before the copy, not while it runs.
+pub fn copy_to_user(dst: VirtAddr, src: &[u8]) -> Result<(), UserCopyError> {
+    check_user_range(dst, src.len(), true)?;
+    with_user_access(|| unsafe {
+        core::ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr::<u8>(), src.len())
+    });
+    Ok(())
+}
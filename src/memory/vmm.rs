@@ -0,0 +1,504 @@
+//Gestionnaire de l'espace d'adressage virtuel du noyau. Au lieu de choisir des adresses a la main (comme l'ancien
+//HEAP_START = 0x_4444_4444_0000), on demande une plage a ce gestionnaire : il possede la moitie haute de l'espace d'adressage,
+//garantit que les regions ne se chevauchent pas et retient pour chacune ses drapeaux et son usage. Les regions sont gardees
+//triees par adresse dans un tableau de taille fixe, car le tas peut lui-meme avoir besoin d'une region.
+
+use super::huge;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use x86_64::{
+    structures::idt::PageFaultErrorCode,
+    structures::paging::{
+        mapper::MapToError,
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+        PageTableFlags, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// Start of the kernel half of the address space.
+pub const KERNEL_SPACE_START: u64 = 0xffff_8000_0000_0000;
+/// End (exclusive) of the managed kernel space. The last page is left unused
+/// so that region ends never overflow.
+pub const KERNEL_SPACE_END: u64 = 0xffff_ffff_ffff_f000;
+/// Maximum number of regions.
+pub const MAX_REGIONS: usize = 64;
+
+/// Number of unmapped guard pages at the bottom of every `Stack` region, so
+/// that a stack overflow faults instead of overwriting the memory below.
+pub const STACK_GUARD_PAGES: u64 = 1;
+
+/// Size of the range covered by one level 4 entry (512 GiB).
+const P4_ENTRY_SIZE: u64 = 1 << 39;
+const PAGE_SIZE: u64 = 4096;
+
+/// What a region is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Heap,
+    Stack,
+    /// Device memory, mapped to a fixed physical range.
+    Mmio,
+    Module,
+    /// Already in use when the manager was created (e.g. by the bootloader).
+    Reserved,
+}
+
+/// A range of kernel virtual memory handed out by the manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: VirtAddr,
+    pub size: u64,
+    /// Flags used when the region is mapped.
+    pub flags: PageTableFlags,
+    pub kind: RegionKind,
+    /// Whether pages of the region are mapped, through `map_region` or by a
+    /// page fault in a lazy region.
+    pub mapped: bool,
+    /// Whether pages are mapped on first access by the page fault handler.
+    pub lazy: bool,
+}
+
+impl Region {
+    const EMPTY: Region = Region {
+        start: VirtAddr::zero(),
+        size: 0,
+        flags: PageTableFlags::empty(),
+        kind: RegionKind::Reserved,
+        mapped: false,
+        lazy: false,
+    };
+
+    /// Returns the first address after the region.
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr < self.end()
+    }
+
+    /// Returns the size of the guard pages at the bottom of the region.
+    pub fn guard_size(&self) -> u64 {
+        match self.kind {
+            RegionKind::Stack => STACK_GUARD_PAGES * PAGE_SIZE,
+            _ => 0,
+        }
+    }
+
+    /// Returns whether `addr` lies in the guard pages of the region.
+    pub fn in_guard(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr < self.start + self.guard_size()
+    }
+
+    /// Returns the start and size of the part of the region that gets
+    /// mapped, i.e. everything above the guard pages.
+    pub fn mapped_range(&self) -> (VirtAddr, u64) {
+        (self.start + self.guard_size(), self.size - self.guard_size())
+    }
+}
+
+/// Errors of the `VirtualMemoryManager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    /// No free range is large enough.
+    OutOfAddressSpace,
+    /// `MAX_REGIONS` regions exist already.
+    TooManyRegions,
+    /// The requested range is not page aligned, empty or outside kernel space.
+    InvalidRange,
+    /// The requested range overlaps an existing region.
+    Overlap,
+    /// No region starts at the given address.
+    NoSuchRegion,
+    /// The region must be unmapped first.
+    StillMapped,
+    /// `memory::init_kernel_memory` was not called yet.
+    Uninitialized,
+    /// No frame was left to back a page of the region.
+    FrameAllocationFailed,
+    /// A page of the region is mapped already.
+    AlreadyMapped,
+}
+
+impl<S: PageSize> From<MapToError<S>> for VmError {
+    fn from(err: MapToError<S>) -> Self {
+        match err {
+            MapToError::FrameAllocationFailed => VmError::FrameAllocationFailed,
+            MapToError::ParentEntryHugePage | MapToError::PageAlreadyMapped(_) => {
+                VmError::AlreadyMapped
+            }
+        }
+    }
+}
+
+/// Allocator of non-overlapping ranges in the kernel half of the address space.
+pub struct VirtualMemoryManager {
+    /// The regions, sorted by start address. Only the first `len` are valid.
+    regions: [Region; MAX_REGIONS],
+    len: usize,
+}
+
+impl Default for VirtualMemoryManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualMemoryManager {
+    /// Creates a manager with the whole kernel space free.
+    pub const fn new() -> Self {
+        VirtualMemoryManager {
+            regions: [Region::EMPTY; MAX_REGIONS],
+            len: 0,
+        }
+    }
+
+    /// Reserves the ranges of the kernel half that are already used in the
+    /// given level 4 table, so that they are never handed out.
+    pub fn reserve_used_entries(&mut self, level_4_table: &PageTable) {
+        for index in 256..512 {
+            let entry = &level_4_table[index];
+            if entry.is_unused() {
+                continue;
+            }
+            // les adresses de la moitie haute sont etendues par le signe a partir du bit 47
+            let start = 0xffff_0000_0000_0000 | (index as u64 * P4_ENTRY_SIZE);
+            let size = P4_ENTRY_SIZE.min(KERNEL_SPACE_END - start);
+            self.reserve(VirtAddr::new(start), size, RegionKind::Reserved, entry.flags())
+                .expect("failed to reserve a used level 4 entry");
+        }
+    }
+
+    /// Records a region at a fixed address.
+    pub fn reserve(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmError> {
+        let region = Region {
+            start,
+            size,
+            flags,
+            kind,
+            mapped: false,
+            lazy: false,
+        };
+        if size == 0
+            || !start.is_aligned(PAGE_SIZE)
+            || !size.is_multiple_of(PAGE_SIZE)
+            || start.as_u64() < KERNEL_SPACE_START
+            || start.as_u64().checked_add(size).is_none_or(|end| end > KERNEL_SPACE_END)
+        {
+            return Err(VmError::InvalidRange);
+        }
+
+        let index = self.regions().position(|r| r.start > start).unwrap_or(self.len);
+        let overlaps_previous = index > 0 && self.regions[index - 1].end() > start;
+        let overlaps_next = index < self.len && self.regions[index].start < region.end();
+        if overlaps_previous || overlaps_next {
+            return Err(VmError::Overlap);
+        }
+        self.insert(index, region)?;
+        Ok(region)
+    }
+
+    /// Finds a free range of `size` bytes (rounded up to whole pages) and
+    /// records it as a region.
+    ///
+    /// Regions of 2 MiB or more are aligned on 2 MiB, so that they can be
+    /// mapped with huge pages.
+    pub fn allocate(
+        &mut self,
+        size: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmError> {
+        let align = if size >= Size2MiB::SIZE {
+            Size2MiB::SIZE
+        } else {
+            PAGE_SIZE
+        };
+        self.allocate_aligned(size, align, kind, flags)
+    }
+
+    /// Like `allocate`, with a start address aligned on `align` (a power of
+    /// two, at least `PAGE_SIZE`).
+    pub fn allocate_aligned(
+        &mut self,
+        size: u64,
+        align: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmError> {
+        if size == 0 || !align.is_power_of_two() || align < PAGE_SIZE {
+            return Err(VmError::InvalidRange);
+        }
+        let size = size
+            .checked_add(PAGE_SIZE - 1)
+            .ok_or(VmError::OutOfAddressSpace)?
+            & !(PAGE_SIZE - 1);
+
+        // premier trou assez grand entre deux regions
+        let mut gap_start = KERNEL_SPACE_START;
+        for index in 0..=self.len {
+            let gap_end = if index < self.len {
+                self.regions[index].start.as_u64()
+            } else {
+                KERNEL_SPACE_END
+            };
+            let aligned_start = align_up(gap_start, align);
+            if gap_end >= aligned_start && gap_end - aligned_start >= size {
+                let region = Region {
+                    start: VirtAddr::new(aligned_start),
+                    size,
+                    flags,
+                    kind,
+                    mapped: false,
+                    lazy: false,
+                };
+                self.insert(index, region)?;
+                return Ok(region);
+            }
+            if index < self.len {
+                gap_start = self.regions[index].end().as_u64();
+            }
+        }
+        Err(VmError::OutOfAddressSpace)
+    }
+
+    /// Like `allocate`, but the pages of the region are only backed by frames
+    /// when they are first accessed (see `resolve_page_fault`).
+    pub fn allocate_lazy(
+        &mut self,
+        size: u64,
+        kind: RegionKind,
+        flags: PageTableFlags,
+    ) -> Result<Region, VmError> {
+        let region = self.allocate(size, kind, flags)?;
+        let index = self.index_of(region.start)?;
+        self.regions[index].lazy = true;
+        Ok(self.regions[index])
+    }
+
+    /// Forgets the region starting at `start`, making its range available again.
+    pub fn release(&mut self, start: VirtAddr) -> Result<Region, VmError> {
+        let index = self.index_of(start)?;
+        let region = self.regions[index];
+        if region.mapped {
+            return Err(VmError::StillMapped);
+        }
+        self.regions.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        Ok(region)
+    }
+
+    /// Returns the region containing `addr`.
+    pub fn find(&self, addr: VirtAddr) -> Option<&Region> {
+        self.regions().find(|region| region.contains(addr))
+    }
+
+    /// Returns all regions, sorted by address.
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions[..self.len].iter()
+    }
+
+    /// Maps every page of the region starting at `start` to a newly
+    /// allocated frame.
+    ///
+    /// If a page cannot be mapped, the pages mapped so far are unmapped again.
+    /// Only this prefix is unmapped: the rest of the region was not touched.
+    pub fn map_region<A>(
+        &mut self,
+        start: VirtAddr,
+        mapper: &mut OffsetPageTable,
+        frame_allocator: &mut A,
+    ) -> Result<Region, VmError>
+    where
+        A: FrameAllocator<Size4KiB>
+            + FrameAllocator<Size2MiB>
+            + FrameDeallocator<Size4KiB>
+            + FrameDeallocator<Size2MiB>,
+    {
+        let index = self.index_of(start)?;
+        let region = self.regions[index];
+        let (mapped_start, mapped_size) = region.mapped_range();
+        if let Err(err) = huge::map_range(mapper, mapped_start, mapped_size, region.flags, frame_allocator) {
+            unsafe { huge::unmap_range(mapper, mapped_start, err.mapped, Some(frame_allocator)) };
+            return Err(err.error);
+        }
+        self.regions[index].mapped = true;
+        Ok(self.regions[index])
+    }
+
+    /// Maps the region starting at `start` to the physical range starting at
+    /// `phys_start`, e.g. for MMIO. Huge pages are used where the region and
+    /// `phys_start` are aligned for them.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the physical range may be accessed with
+    /// the region's flags.
+    pub unsafe fn map_region_to(
+        &mut self,
+        start: VirtAddr,
+        phys_start: PhysAddr,
+        mapper: &mut OffsetPageTable,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<Region, VmError> {
+        let index = self.index_of(start)?;
+        let region = self.regions[index];
+        let (mapped_start, mapped_size) = region.mapped_range();
+        let phys_start = phys_start + (mapped_start - region.start);
+        huge::map_physical_range(mapper, mapped_start, phys_start, mapped_size, region.flags, frame_allocator)?;
+        self.regions[index].mapped = true;
+        Ok(self.regions[index])
+    }
+
+    /// Unmaps every mapped page of the region starting at `start`. Frames are
+    /// given back to `frame_deallocator`, except for `Mmio` regions.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the region is not used anymore.
+    pub unsafe fn unmap_region<D>(
+        &mut self,
+        start: VirtAddr,
+        mapper: &mut OffsetPageTable,
+        frame_deallocator: &mut D,
+    ) -> Result<Region, VmError>
+    where
+        D: FrameDeallocator<Size4KiB> + FrameDeallocator<Size2MiB>,
+    {
+        let index = self.index_of(start)?;
+        let region = self.regions[index];
+        let deallocator = match region.kind {
+            RegionKind::Mmio => None,
+            _ => Some(frame_deallocator),
+        };
+        let (mapped_start, mapped_size) = region.mapped_range();
+        huge::unmap_range(mapper, mapped_start, mapped_size, deallocator);
+        self.regions[index].mapped = false;
+        Ok(self.regions[index])
+    }
+
+    fn find_mut(&mut self, addr: VirtAddr) -> Option<&mut Region> {
+        self.regions[..self.len]
+            .iter_mut()
+            .find(|region| region.contains(addr))
+    }
+
+    fn index_of(&self, start: VirtAddr) -> Result<usize, VmError> {
+        self.regions()
+            .position(|region| region.start == start)
+            .ok_or(VmError::NoSuchRegion)
+    }
+
+    fn insert(&mut self, index: usize, region: Region) -> Result<(), VmError> {
+        if self.len == MAX_REGIONS {
+            return Err(VmError::TooManyRegions);
+        }
+        self.regions.copy_within(index..self.len, index + 1);
+        self.regions[index] = region;
+        self.len += 1;
+        Ok(())
+    }
+}
+
+/// Number of page faults resolved by `resolve_page_fault`.
+static DEMAND_FAULTS: AtomicUsize = AtomicUsize::new(0);
+
+//Appelee par le gestionnaire de page fault : si l'adresse tombe dans une region paresseuse, on mappe une frame remise a zero
+//et l'instruction fautive est reexecutee au retour de l'interruption. Les verrous sont pris avec try_lock, car la faute a pu
+//survenir pendant qu'ils etaient tenus ; dans ce cas la faute est consideree comme fatale plutot que de bloquer pour toujours.
+
+/// Backs the page containing `addr` with a zeroed frame if `addr` lies in a
+/// lazy region.
+///
+/// Returns `false` for faults that cannot be resolved this way: protection
+/// violations, user mode accesses, addresses outside of lazy regions or in
+/// guard pages, writes
+/// to read-only regions and faults raised while the memory locks are held.
+pub fn resolve_page_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    if error_code.intersects(
+        PageFaultErrorCode::PROTECTION_VIOLATION
+            | PageFaultErrorCode::USER_MODE
+            | PageFaultErrorCode::MALFORMED_TABLE,
+    ) {
+        return false;
+    }
+
+    let mut kernel_memory = match super::KERNEL_MEMORY.try_lock() {
+        Some(kernel_memory) => kernel_memory,
+        None => return false,
+    };
+    let memory = match kernel_memory.as_mut() {
+        Some(memory) => memory,
+        None => return false,
+    };
+    let mut vmm = match VMM.try_lock() {
+        Some(vmm) => vmm,
+        None => return false,
+    };
+    let region = match vmm.find_mut(addr) {
+        Some(region) if region.lazy && !region.in_guard(addr) => region,
+        _ => return false,
+    };
+    if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE)
+        && !region.flags.contains(PageTableFlags::WRITABLE)
+    {
+        return false;
+    }
+
+    let physical_memory_offset = match super::physical_memory_offset() {
+        Some(offset) => offset,
+        None => return false,
+    };
+    let page = Page::<Size4KiB>::containing_address(addr);
+    let frame = match memory.frame_allocator.allocate_frame() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    // la frame est remise a zero par le mapping de la memoire physique, avant d'etre visible : la region peut etre en lecture
+    // seule, et une ecriture par la nouvelle page ferait une nouvelle faute dans le gestionnaire
+    let zeroed = physical_memory_offset + frame.start_address().as_u64();
+    unsafe { ptr::write_bytes(zeroed.as_mut_ptr::<u8>(), 0, Size4KiB::SIZE as usize) };
+    match unsafe { memory.mapper.map_to(page, frame, region.flags, &mut memory.frame_allocator) } {
+        Ok(flush) => flush.flush(),
+        Err(_) => {
+            unsafe { memory.frame_allocator.deallocate_frame(frame) };
+            return false;
+        }
+    }
+    region.mapped = true;
+    DEMAND_FAULTS.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// Returns the number of page faults resolved by mapping a lazy page.
+pub fn demand_faults() -> usize {
+    DEMAND_FAULTS.load(Ordering::Relaxed)
+}
+
+//pris apres KERNEL_MEMORY et avant le verrou du tas (voir memory.rs)
+static VMM: spin::Mutex<VirtualMemoryManager> = spin::Mutex::new(VirtualMemoryManager::new());
+
+/// Runs `f` with exclusive access to the kernel's `VirtualMemoryManager`.
+///
+/// Interrupts are disabled while the lock is held. When both are needed, take
+/// the `KernelMemory` lock (`with_kernel_memory`) first.
+pub fn with_vmm<R>(f: impl FnOnce(&mut VirtualMemoryManager) -> R) -> R {
+    x86_64::instructions::interrupts::without_interrupts(|| f(&mut VMM.lock()))
+}
+
+/// Like `with_vmm`, but returns `None` instead of waiting when the manager is
+/// locked, for use in fault handlers.
+pub fn try_with_vmm<R>(f: impl FnOnce(&mut VirtualMemoryManager) -> R) -> Option<R> {
+    VMM.try_lock().map(|mut vmm| f(&mut vmm))
+}
+
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
@@ -0,0 +1,346 @@
+//Parcours des tables de pages actives. Les tables sont lues a travers le mappage de toute la memoire physique (comme le fait
+//OffsetPageTable), mais entree par entree et sans prendre de reference sur elles : le parcours ne prend aucun verrou et peut donc
+//etre utilise depuis un gestionnaire de faute, au prix d'une vue eventuellement incoherente si les tables sont modifiees en meme temps.
+
+use core::fmt;
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{PageTableFlags, PageTableIndex},
+    PhysAddr, VirtAddr,
+};
+
+/// Bits of a page table entry that hold the physical address.
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+//ces bits sont modifies par le processeur a chaque acces, ils ne doivent pas couper une plage en deux
+const VOLATILE_FLAGS: PageTableFlags = PageTableFlags::ACCESSED.union(PageTableFlags::DIRTY);
+
+/// The size of the page mapping a range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingSize {
+    Page4KiB,
+    Page2MiB,
+    Page1GiB,
+}
+
+impl MappingSize {
+    /// Returns the size of a page in bytes.
+    pub fn bytes(self) -> u64 {
+        match self {
+            MappingSize::Page4KiB => 4096,
+            MappingSize::Page2MiB => 2 * 1024 * 1024,
+            MappingSize::Page1GiB => 1024 * 1024 * 1024,
+        }
+    }
+
+    /// Returns the page size mapped by a leaf entry of the given level.
+    fn of_level(level: u8) -> Self {
+        match level {
+            1 => MappingSize::Page4KiB,
+            2 => MappingSize::Page2MiB,
+            3 => MappingSize::Page1GiB,
+            _ => unreachable!("level {} cannot map a page", level),
+        }
+    }
+}
+
+impl fmt::Display for MappingSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MappingSize::Page4KiB => "4K",
+            MappingSize::Page2MiB => "2M",
+            MappingSize::Page1GiB => "1G",
+        })
+    }
+}
+
+/// A contiguous virtual range mapped to a contiguous physical range by pages
+/// of the same size and flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedRange {
+    pub virt: VirtAddr,
+    pub phys: PhysAddr,
+    pub size: u64,
+    pub page_size: MappingSize,
+    /// The flags of the leaf entries, without `ACCESSED` and `DIRTY`.
+    pub flags: PageTableFlags,
+}
+
+impl MappedRange {
+    /// Returns the first virtual address after the range.
+    pub fn end(&self) -> VirtAddr {
+        self.virt + self.size
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.virt <= addr && addr < self.end()
+    }
+
+    /// Returns whether `next` continues this range without any gap.
+    fn is_continued_by(&self, next: &MappedRange) -> bool {
+        next.page_size == self.page_size
+            && next.flags == self.flags
+            && next.virt == self.end()
+            && next.phys == self.phys + self.size
+    }
+}
+
+/// Writes the flags as `rwxu` columns, `-` standing for a missing right, and
+/// `H` for a huge page.
+struct FlagsColumns(PageTableFlags);
+
+impl fmt::Display for FlagsColumns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = self.0;
+        let column = |flag, c| if flags.contains(flag) { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}{}{}",
+            column(PageTableFlags::PRESENT, 'r'),
+            column(PageTableFlags::WRITABLE, 'w'),
+            if flags.contains(PageTableFlags::NO_EXECUTE) { '-' } else { 'x' },
+            column(PageTableFlags::USER_ACCESSIBLE, 'u'),
+            column(PageTableFlags::HUGE_PAGE, 'H'),
+        )
+    }
+}
+
+impl fmt::Display for MappedRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#018x}-{:#018x} -> {:#012x} {:>8} KiB {} {}",
+            self.virt.as_u64(),
+            self.end().as_u64(),
+            self.phys.as_u64(),
+            self.size / 1024,
+            self.page_size,
+            FlagsColumns(self.flags),
+        )
+    }
+}
+
+/// The result of walking the page tables for one address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Translation {
+    /// The address is mapped by a page of `page_size`, whose entry was found
+    /// in a table of `level` (1 for a 4 KiB page, 2 or 3 for a huge page).
+    Mapped {
+        phys: PhysAddr,
+        page_size: MappingSize,
+        level: u8,
+        flags: PageTableFlags,
+    },
+    /// The walk stopped at `level` because the entry there is not present.
+    NotMapped { level: u8 },
+}
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Translation::Mapped {
+                phys,
+                page_size,
+                level,
+                flags,
+            } => write!(
+                f,
+                "{:#x} ({} page at level {}, {})",
+                phys.as_u64(),
+                page_size,
+                level,
+                FlagsColumns(flags)
+            ),
+            Translation::NotMapped { level } => write!(f, "not mapped (level {} entry not present)", level),
+        }
+    }
+}
+
+/// Reads entry `index` of the table stored in frame `table`.
+///
+/// This function is unsafe because the caller must guarantee that `table`
+/// is a page table and that the physical memory is mapped at `offset`.
+unsafe fn read_entry(offset: VirtAddr, table: PhysAddr, index: usize) -> (PageTableFlags, PhysAddr) {
+    let entries: *const u64 = (offset + table.as_u64()).as_ptr();
+    let entry = core::ptr::read_volatile(entries.add(index));
+    (PageTableFlags::from_bits_truncate(entry), PhysAddr::new(entry & ADDRESS_MASK))
+}
+
+fn physical_memory_offset() -> VirtAddr {
+    super::physical_memory_offset().expect("memory::init was not called")
+}
+
+/// Walks the active page tables to translate `addr`, reporting the level at
+/// which the walk stopped.
+///
+/// Panics if `memory::init` was not called.
+pub fn translate(addr: VirtAddr) -> Translation {
+    let offset = physical_memory_offset();
+    let indices: [PageTableIndex; 4] = [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
+    let mut table = Cr3::read().0.start_address();
+
+    for (level, index) in (1..=4u8).rev().zip(indices) {
+        let (flags, entry_addr) = unsafe { read_entry(offset, table, usize::from(index)) };
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return Translation::NotMapped { level };
+        }
+        // une entree de niveau 4 ne peut pas mapper de page, le bit HUGE_PAGE y est reserve
+        if level == 1 || (level < 4 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+            let page_size = MappingSize::of_level(level);
+            return Translation::Mapped {
+                phys: entry_addr.align_down(page_size.bytes()) + (addr.as_u64() & (page_size.bytes() - 1)),
+                page_size,
+                level,
+                flags,
+            };
+        }
+        table = entry_addr;
+    }
+    unreachable!()
+}
+
+/// Returns the access rights the CPU actually grants for `addr`, or `None`
+/// if it is not mapped.
+///
+/// `WRITABLE` and `USER_ACCESSIBLE` are only kept if every level of the walk
+/// sets them, and `NO_EXECUTE` is set if any level does. The other flags
+/// come from the final entry.
+pub fn effective_flags(addr: VirtAddr) -> Option<PageTableFlags> {
+    let offset = physical_memory_offset();
+    let indices: [PageTableIndex; 4] = [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
+    let mut table = Cr3::read().0.start_address();
+    let inherited = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let mut allowed = inherited;
+    let mut no_execute = PageTableFlags::empty();
+
+    for (level, index) in (1..=4u8).rev().zip(indices) {
+        let (flags, entry_addr) = unsafe { read_entry(offset, table, usize::from(index)) };
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        allowed &= flags;
+        no_execute |= flags & PageTableFlags::NO_EXECUTE;
+        if level == 1 || (level < 4 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+            return Some((flags - inherited) | allowed | no_execute);
+        }
+        table = entry_addr;
+    }
+    unreachable!()
+}
+
+/// Iterator over the pages mapped by the active page tables, one leaf entry at
+/// a time, in increasing virtual address order.
+struct Leaves {
+    offset: VirtAddr,
+    //tables[0] est la table de niveau 4, tables[depth] celle en cours de parcours
+    tables: [PhysAddr; 4],
+    indices: [usize; 4],
+    depth: usize,
+}
+
+impl Leaves {
+    fn virt_addr(&self) -> VirtAddr {
+        let address = self.indices[..=self.depth]
+            .iter()
+            .zip([39, 30, 21, 12])
+            .fold(0, |address, (&index, shift)| address | (index as u64) << shift);
+        // new_truncate etend le bit 47 sur les bits de poids fort
+        VirtAddr::new_truncate(address)
+    }
+}
+
+impl Iterator for Leaves {
+    type Item = MappedRange;
+
+    fn next(&mut self) -> Option<MappedRange> {
+        loop {
+            if self.indices[self.depth] == 512 {
+                if self.depth == 0 {
+                    return None;
+                }
+                self.depth -= 1;
+                self.indices[self.depth] += 1;
+                continue;
+            }
+
+            let (flags, entry_addr) =
+                unsafe { read_entry(self.offset, self.tables[self.depth], self.indices[self.depth]) };
+            if !flags.contains(PageTableFlags::PRESENT) {
+                self.indices[self.depth] += 1;
+                continue;
+            }
+
+            let level = 4 - self.depth as u8;
+            if level == 1 || (level < 4 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+                let page_size = MappingSize::of_level(level);
+                let leaf = MappedRange {
+                    virt: self.virt_addr(),
+                    // le bit 12 d'une entree de grande page est le bit PAT, pas un bit d'adresse
+                    phys: entry_addr.align_down(page_size.bytes()),
+                    size: page_size.bytes(),
+                    page_size,
+                    flags: flags - VOLATILE_FLAGS,
+                };
+                self.indices[self.depth] += 1;
+                return Some(leaf);
+            }
+
+            self.depth += 1;
+            self.tables[self.depth] = entry_addr;
+            self.indices[self.depth] = 0;
+        }
+    }
+}
+
+/// Iterator over the mapped ranges of the active address space, see
+/// `mappings`.
+pub struct Mappings {
+    leaves: Leaves,
+    pending: Option<MappedRange>,
+}
+
+impl Iterator for Mappings {
+    type Item = MappedRange;
+
+    fn next(&mut self) -> Option<MappedRange> {
+        let mut range = self.pending.take().or_else(|| self.leaves.next())?;
+        for leaf in &mut self.leaves {
+            if !range.is_continued_by(&leaf) {
+                self.pending = Some(leaf);
+                break;
+            }
+            range.size += leaf.size;
+        }
+        Some(range)
+    }
+}
+
+/// Returns an iterator over the ranges mapped by the active page tables,
+/// sorted by virtual address.
+///
+/// Consecutive pages are merged into one range as long as they have the
+/// same size and flags and map consecutive physical memory.
+///
+/// Panics if `memory::init` was not called.
+pub fn mappings() -> Mappings {
+    Mappings {
+        leaves: Leaves {
+            offset: physical_memory_offset(),
+            tables: [Cr3::read().0.start_address(); 4],
+            indices: [0; 4],
+            depth: 0,
+        },
+        pending: None,
+    }
+}
+
+/// Prints the mapped ranges of the active address space to the serial port.
+pub fn print_mappings() {
+    crate::serial_println!("virtual range -> physical base, size, page size, flags (rwxuH):");
+    let mut total = 0;
+    for range in mappings() {
+        crate::serial_println!("{}", range);
+        total += range.size;
+    }
+    crate::serial_println!("{} KiB mapped", total / 1024);
+}
@@ -182,6 +182,96 @@ pub fn _print(args: fmt::Arguments) {
     });
 }
 
+//Page de plantage : tout l'ecran est repeint en blanc sur rouge, le titre sur la premiere ligne et le texte en dessous, coupe a
+//80 colonnes ; ce qui depasse la derniere ligne est perdu (le port serie a le texte complet).
+struct CrashPage<'a> {
+    buffer: &'a mut Buffer,
+    row: usize,
+    column: usize,
+    color_code: ColorCode,
+}
+
+impl CrashPage<'_> {
+    fn fill(&mut self, row: usize, color_code: ColorCode) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code,
+        };
+        for col in 0..BUFFER_WIDTH {
+            self.buffer.chars[row][col].write(blank);
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        if byte == b'\n' || self.column >= BUFFER_WIDTH {
+            self.row += 1;
+            self.column = 0;
+            if byte == b'\n' {
+                return;
+            }
+        }
+        if self.row < BUFFER_HEIGHT {
+            self.buffer.chars[self.row][self.column].write(ScreenChar {
+                ascii_character: byte,
+                color_code: self.color_code,
+            });
+        }
+        self.column += 1;
+    }
+}
+
+impl fmt::Write for CrashPage<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            match byte {
+                0x20..=0x7e | b'\n' => self.write_byte(byte),
+                _ => self.write_byte(0xfe),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the whole screen with a "kernel crash" page showing `title` and
+/// `body`.
+///
+/// Only meant for fatal paths: the lock of `WRITER` is forced in case the
+/// crash interrupted a `print!`, and the normal output is lost.
+pub fn crash_screen(title: &str, body: fmt::Arguments) {
+    use core::fmt::Write;
+
+    let mut writer = unsafe {
+        WRITER.force_unlock();
+        WRITER.lock()
+    };
+    let title_color = ColorCode::new(Color::Red, Color::White);
+    let mut page = CrashPage {
+        buffer: &mut *writer.buffer,
+        row: 0,
+        column: 0,
+        color_code: title_color,
+    };
+    page.fill(0, title_color);
+    for row in 1..BUFFER_HEIGHT {
+        page.fill(row, ColorCode::new(Color::White, Color::Red));
+    }
+    // titre centre sur la premiere ligne
+    page.column = BUFFER_WIDTH.saturating_sub(title.len()) / 2;
+    page.write_str(title).unwrap();
+
+    page.row = 2;
+    page.column = 0;
+    page.color_code = ColorCode::new(Color::White, Color::Red);
+    page.write_fmt(body).unwrap();
+
+    // la derniere ligne est reservee au message d'arret, meme si le texte la depasse
+    page.row = BUFFER_HEIGHT - 1;
+    page.column = 0;
+    page.color_code = ColorCode::new(Color::Yellow, Color::Red);
+    page.fill(BUFFER_HEIGHT - 1, page.color_code);
+    page.write_str("The system has been halted.").unwrap();
+}
+
 //Maintenant que nous avons un cadre de test fonctionnel, nous pouvons créer quelques tests pour notre implémentation de tampon VGA. 
 
 //Un test très simple pour vérifier qu'il printlnfonctionne sans paniquer 
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::acpi;
+use blog_os::memory;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test acpi
+    blog_os::init();
+    unsafe { memory::init(VirtAddr::new(boot_info.physical_memory_offset)) };
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+#[test_case]
+fn rsdp_is_found_and_valid() {
+    let rsdp = acpi::find_rsdp().expect("no valid RSDP");
+    assert!(rsdp.address.as_u64() < 0x10_0000);
+    assert_ne!(rsdp.rsdt, PhysAddr::new(0));
+    assert_eq!(rsdp.xsdt.is_some(), rsdp.revision >= 2);
+}
+
+#[test_case]
+fn root_table_lists_the_standard_tables() {
+    let rsdp = acpi::find_rsdp().unwrap();
+    let mut tables = acpi::tables(&rsdp).expect("invalid root table");
+    // QEMU fournit toujours la FADT et la MADT
+    assert!(tables.any(|(signature, _)| signature == *b"FACP"));
+    assert!(acpi::find_table(b"APIC").is_some());
+    assert!(acpi::find_table(b"NONE").is_none());
+}
+
+#[test_case]
+fn fadt_gives_the_power_management_ports() {
+    let fadt = acpi::fadt().expect("no FADT");
+    // le SCI est une interruption ISA (9 sous QEMU) et le bloc PM1a de controle est toujours present
+    assert!(fadt.sci_interrupt < 16);
+    assert!(fadt.pm1a_control_block.is_some());
+    assert!(fadt.pm_timer_block.is_some());
+    assert_ne!(fadt.dsdt, PhysAddr::new(0));
+}
+
+#[test_case]
+fn init_keeps_the_decoded_tables() {
+    assert!(acpi::decoded_tables().is_none());
+    let tables = acpi::init().expect("ACPI init failed");
+    assert!(tables.madt.is_some());
+    assert_eq!(tables.fadt, acpi::fadt());
+    assert_eq!(tables.hpet, acpi::hpet());
+    if let Some(hpet) = tables.hpet {
+        assert!(hpet.comparators >= 3);
+        assert_ne!(hpet.address, PhysAddr::new(0));
+    }
+    assert_eq!(acpi::decoded_tables().map(|kept| kept.rsdp), Some(tables.rsdp));
+}
@@ -0,0 +1,184 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use blog_os::allocator;
+use blog_os::memory::{
+    self,
+    address_space::{self, AddressSpace},
+    cow,
+    vmm::{self, RegionKind},
+    walk::{self, Translation},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test address_space
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+const USER_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::USER_ACCESSIBLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+fn free_frames() -> usize {
+    memory::with_kernel_memory(|memory| memory.frame_allocator.free_frames()).unwrap()
+}
+
+fn map_page(space: &mut AddressSpace, page: Page, flags: PageTableFlags) {
+    space
+        .with_mapper(|mapper, frame_allocator| unsafe {
+            let frame = FrameAllocator::<Size4KiB>::allocate_frame(frame_allocator).unwrap();
+            mapper.map_to(page, frame, flags, frame_allocator).unwrap().flush();
+        })
+        .unwrap();
+}
+
+#[test_case]
+fn kernel_mappings_are_shared() {
+    let value = Box::new(41);
+    let space = AddressSpace::new().unwrap();
+    unsafe { space.activate() };
+    assert!(space.is_active());
+    // le tas, la pile et le code du noyau restent accessibles
+    let other = Box::new(*value + 1);
+    assert_eq!(*other, 42);
+    address_space::activate_kernel();
+    assert!(!space.is_active());
+}
+
+#[test_case]
+fn user_mappings_are_private() {
+    let mut space = AddressSpace::new().unwrap();
+    let addr = space.first_user_address().unwrap();
+    let page = Page::containing_address(addr);
+    map_page(&mut space, page, USER_FLAGS);
+
+    unsafe { space.activate() };
+    blog_os::cpu::with_user_access(|| unsafe {
+        addr.as_mut_ptr::<u64>().write_volatile(7);
+        assert_eq!(addr.as_ptr::<u64>().read_volatile(), 7);
+    });
+    address_space::activate_kernel();
+
+    assert_eq!(walk::translate(addr), Translation::NotMapped { level: 4 });
+}
+
+#[test_case]
+fn kernel_regions_created_later_are_visible() {
+    let space = AddressSpace::new().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    let region = memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| {
+            let region = vmm.allocate(4096, RegionKind::Module, flags).unwrap();
+            vmm.map_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap()
+        })
+    })
+    .unwrap();
+    unsafe { region.start.as_mut_ptr::<u64>().write_volatile(5) };
+
+    unsafe { space.activate() };
+    assert_eq!(unsafe { region.start.as_ptr::<u64>().read_volatile() }, 5);
+    address_space::activate_kernel();
+
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| unsafe {
+            vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap();
+            vmm.release(region.start).unwrap();
+        })
+    })
+    .unwrap();
+}
+
+#[test_case]
+fn drop_frees_user_pages_and_tables() {
+    let before = free_frames();
+    {
+        let mut space = AddressSpace::new().unwrap();
+        let first = Page::containing_address(space.first_user_address().unwrap());
+        map_page(&mut space, first, USER_FLAGS);
+        map_page(&mut space, first + 600, USER_FLAGS); // dans une autre table de niveau 1
+        unsafe { space.activate() };
+        // detruit pendant qu'il est actif : le noyau reprend sa propre table
+    }
+    assert_eq!(free_frames(), before);
+}
+
+//Une page du noyau partagee en copie a l'ecriture avec un autre espace d'adressage : l'ecriture faite pendant que cet espace
+//est actif lui donne sa propre copie, la page du noyau ne change pas.
+#[test_case]
+fn copy_on_write_between_address_spaces() {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    let region = memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| {
+            let region = vmm.allocate(4096, RegionKind::Module, flags).unwrap();
+            vmm.map_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap()
+        })
+    })
+    .unwrap();
+    let kernel_ptr = region.start.as_mut_ptr::<u64>();
+    unsafe { kernel_ptr.write_volatile(1) };
+
+    let mut space = AddressSpace::new().unwrap();
+    let shared = space.first_user_address().unwrap();
+    let (frame, shared_flags) = memory::with_kernel_memory(|memory| {
+        cow::mark_shared(&mut memory.mapper, Page::containing_address(region.start))
+    })
+    .unwrap()
+    .unwrap();
+    space
+        .with_mapper(|mapper, frame_allocator| unsafe {
+            cow::map_shared(mapper, Page::containing_address(shared), frame, shared_flags, frame_allocator)
+        })
+        .unwrap()
+        .unwrap();
+
+    let faults = cow::cow_faults();
+    unsafe { space.activate() };
+    unsafe { shared.as_mut_ptr::<u64>().write_volatile(2) };
+    assert_eq!(unsafe { shared.as_ptr::<u64>().read_volatile() }, 2);
+    address_space::activate_kernel();
+    assert_eq!(cow::cow_faults(), faults + 1);
+    assert_eq!(unsafe { kernel_ptr.read_volatile() }, 1);
+    drop(space);
+
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| unsafe {
+            vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap();
+            vmm.release(region.start).unwrap();
+        })
+    })
+    .unwrap();
+}
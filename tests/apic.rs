@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::acpi;
+use blog_os::allocator;
+use blog_os::interrupts::{self, apic, InterruptController};
+use blog_os::memory::{self, BitmapFrameAllocator};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test apic
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+/// Waits for `count` more timer interrupts.
+fn wait_for_ticks(count: u64) {
+    let target = interrupts::timer_ticks() + count;
+    while interrupts::timer_ticks() < target {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn madt_describes_the_apics() {
+    let madt = acpi::madt().expect("no MADT");
+    // adresses standard du local APIC et du premier I/O APIC sous QEMU
+    assert_eq!(madt.local_apic_address, PhysAddr::new(0xfee0_0000));
+    let io_apic = madt.io_apics().next().expect("no I/O APIC");
+    assert_eq!(io_apic.address, PhysAddr::new(0xfec0_0000));
+    assert_eq!(io_apic.gsi_base, 0);
+    assert!(madt.local_apic_ids.iter().flatten().count() >= 1);
+    // le PIT (IRQ 0) est relie a la GSI 2
+    assert_eq!(madt.isa_irq(0).0, 2);
+}
+
+#[test_case]
+fn pic_delivers_timer_interrupts() {
+    assert_eq!(interrupts::active_controller(), InterruptController::Pic);
+    assert_eq!(interrupts::select_controller(InterruptController::Pic), InterruptController::Pic);
+    wait_for_ticks(3);
+}
+
+#[test_case]
+fn apic_delivers_timer_interrupts() {
+    assert_eq!(interrupts::select_controller(InterruptController::Apic), InterruptController::Apic);
+    assert!(apic::is_enabled());
+    assert!(apic::local_apic_id().is_some());
+    // plusieurs interruptions : chacune doit avoir ete acquittee aupres du local APIC
+    wait_for_ticks(3);
+}
@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::memory::buddy::{BuddyFrameAllocator, MAX_ORDER};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size2MiB};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+static FRAME_ALLOCATOR: Mutex<Option<BuddyFrameAllocator>> = Mutex::new(None);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test buddy_allocator
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let frame_allocator =
+        unsafe { BuddyFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+fn with_allocator(f: impl FnOnce(&mut BuddyFrameAllocator)) {
+    f(FRAME_ALLOCATOR.lock().as_mut().unwrap())
+}
+
+#[test_case]
+fn blocks_are_aligned_to_their_order() {
+    with_allocator(|allocator| {
+        for order in 0..=MAX_ORDER {
+            let block = allocator.allocate(order).expect("out of memory");
+            let size = 4096u64 << order;
+            assert_eq!(block.start_address().as_u64() % size, 0);
+            unsafe { allocator.deallocate(block, order) };
+        }
+    });
+}
+
+#[test_case]
+fn split_blocks_merge_back() {
+    with_allocator(|allocator| {
+        let free = allocator.free_frames();
+        let large = allocator.free_blocks(MAX_ORDER);
+
+        // splitting a large block for many single frames
+        let mut frames: [Option<PhysFrame>; 32] = [None; 32];
+        for slot in frames.iter_mut() {
+            *slot = allocator.allocate(0);
+        }
+        assert_eq!(allocator.free_frames(), free - frames.len());
+        for (i, a) in frames.iter().enumerate() {
+            for b in &frames[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+
+        for frame in frames.iter() {
+            unsafe { allocator.deallocate(frame.unwrap(), 0) };
+        }
+        assert_eq!(allocator.free_frames(), free);
+        assert!(allocator.free_blocks(MAX_ORDER) >= large);
+    });
+}
+
+#[test_case]
+fn huge_frames_are_contiguous() {
+    with_allocator(|allocator| {
+        let frame: PhysFrame<Size2MiB> = allocator.allocate_frame().expect("out of memory");
+        assert_eq!(frame.start_address().as_u64() % (2 * 1024 * 1024), 0);
+        unsafe { allocator.deallocate_frame(frame) };
+    });
+}
@@ -0,0 +1,153 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::allocator;
+use blog_os::memory::{
+    self, cow,
+    vmm::{self, RegionKind},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test copy_on_write
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+const FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// Two pages of a new region: the first one mapped and filled with `value`,
+/// the second one mapped copy-on-write to the same frame.
+fn shared_pair(value: u64) -> (Page, Page) {
+    let region = vmm::with_vmm(|vmm| vmm.allocate(2 * 4096, RegionKind::Module, FLAGS)).unwrap();
+    let first = Page::containing_address(region.start);
+    let second = first + 1;
+    memory::with_kernel_memory(|memory| unsafe {
+        let frame = FrameAllocator::<Size4KiB>::allocate_frame(&mut memory.frame_allocator).unwrap();
+        memory
+            .mapper
+            .map_to(first, frame, FLAGS, &mut memory.frame_allocator)
+            .unwrap()
+            .flush();
+    })
+    .unwrap();
+    unsafe { first.start_address().as_mut_ptr::<u64>().write_volatile(value) };
+
+    memory::with_kernel_memory(|memory| unsafe {
+        let (frame, flags) = cow::mark_shared(&mut memory.mapper, first).unwrap();
+        cow::map_shared(&mut memory.mapper, second, frame, flags, &mut memory.frame_allocator)
+            .unwrap();
+    })
+    .unwrap();
+    (first, second)
+}
+
+fn frame_of(page: Page) -> PhysFrame {
+    memory::with_kernel_memory(|memory| memory.mapper.translate_page(page).unwrap()).unwrap()
+}
+
+fn flags_of(page: Page) -> PageTableFlags {
+    memory::walk::effective_flags(page.start_address()).unwrap()
+}
+
+fn release(first: Page, second: Page) {
+    memory::with_kernel_memory(|memory| unsafe {
+        cow::unmap_page(&mut memory.mapper, first, &mut memory.frame_allocator).unwrap();
+        cow::unmap_page(&mut memory.mapper, second, &mut memory.frame_allocator).unwrap();
+    })
+    .unwrap();
+    vmm::with_vmm(|vmm| vmm.release(first.start_address())).unwrap();
+}
+
+fn read(page: Page) -> u64 {
+    unsafe { page.start_address().as_ptr::<u64>().read_volatile() }
+}
+
+fn write(page: Page, value: u64) {
+    unsafe { page.start_address().as_mut_ptr::<u64>().write_volatile(value) }
+}
+
+#[test_case]
+fn shared_pages_are_read_only() {
+    let (first, second) = shared_pair(42);
+    assert_eq!(frame_of(first), frame_of(second));
+    assert_eq!(cow::share_count(frame_of(first)), 2);
+    assert_eq!(read(second), 42);
+    for page in [first, second] {
+        assert!(flags_of(page).contains(cow::COW));
+        assert!(!flags_of(page).contains(PageTableFlags::WRITABLE));
+    }
+    release(first, second);
+}
+
+#[test_case]
+fn write_copies_the_frame() {
+    let (first, second) = shared_pair(1);
+    let shared = frame_of(first);
+    let faults = cow::cow_faults();
+
+    write(second, 2);
+    assert_eq!(cow::cow_faults(), faults + 1);
+    assert_ne!(frame_of(second), shared);
+    assert_eq!(read(first), 1);
+    assert_eq!(read(second), 2);
+    assert_eq!(cow::share_count(shared), 1);
+
+    // la premiere page est maintenant seule a mapper la frame : elle la recupere sans copie
+    write(first, 3);
+    assert_eq!(cow::cow_faults(), faults + 2);
+    assert_eq!(frame_of(first), shared);
+    assert!(flags_of(first).contains(PageTableFlags::WRITABLE));
+    assert!(!flags_of(first).contains(cow::COW));
+    assert_eq!(read(first), 3);
+    assert_eq!(read(second), 2);
+    release(first, second);
+}
+
+#[test_case]
+fn frame_is_freed_with_the_last_mapping() {
+    let free_frames = || memory::with_kernel_memory(|memory| memory.frame_allocator.free_frames()).unwrap();
+    let (first, second) = shared_pair(7);
+    let before = free_frames();
+    memory::with_kernel_memory(|memory| unsafe {
+        cow::unmap_page(&mut memory.mapper, second, &mut memory.frame_allocator).unwrap()
+    })
+    .unwrap();
+    assert_eq!(free_frames(), before);
+    assert_eq!(read(first), 7);
+
+    memory::with_kernel_memory(|memory| unsafe {
+        cow::unmap_page(&mut memory.mapper, first, &mut memory.frame_allocator).unwrap()
+    })
+    .unwrap();
+    assert_eq!(free_frames(), before + 1);
+    vmm::with_vmm(|vmm| vmm.release(first.start_address())).unwrap();
+}
@@ -0,0 +1,122 @@
+#![no_std]
+#![no_main]
+//Une exception fatale doit laisser tous les registres dans le rapport et une page de plantage sur l'ecran VGA.
+
+use blog_os::interrupts::exceptions::ExceptionReport;
+use blog_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::fmt::Write;
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+const RAX: u64 = 0x1122_3344_5566_7788;
+const R15: u64 = 0x0f0f_0f0f_0f0f_0f0f;
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    //cargo test --test crash_screen
+    serial_print!("crash_screen::registers_and_crash_page...\t");
+
+    blog_os::init();
+    blog_os::crash::set_crash_hook(check_report);
+
+    unsafe {
+        core::arch::asm!(
+            "mov rax, {rax}",
+            "mov r15, {r15}",
+            "ud2",
+            rax = const RAX,
+            r15 = const R15,
+            out("rax") _,
+            out("r15") _,
+        );
+    }
+
+    serial_println!("[failed]");
+    serial_println!("execution continued after ud2");
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+/// A line of the VGA text buffer, without its colors.
+struct ScreenLine([u8; 80]);
+
+impl ScreenLine {
+    fn read(row: usize) -> (Self, u8) {
+        let buffer = 0xb8000 as *const u16;
+        let mut line = [0; 80];
+        for (col, byte) in line.iter_mut().enumerate() {
+            *byte = unsafe { buffer.add(row * 80 + col).read_volatile() } as u8;
+        }
+        let color = (unsafe { buffer.add(row * 80).read_volatile() } >> 8) as u8;
+        (ScreenLine(line), color)
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        self.0.windows(text.len()).any(|window| window == text.as_bytes())
+    }
+}
+
+fn check_report(report: &ExceptionReport) {
+    let general = report.registers.general;
+    if general.rax != RAX || general.r15 != R15 {
+        fail(format_args!("registers not saved: rax {:#x}, r15 {:#x}", general.rax, general.r15));
+    }
+    // le noyau tourne en mode long avec la pagination, les interruptions etaient actives
+    let mut names = Names { bytes: [0; 128], len: 0 };
+    write!(names, "{} {} {}", report.registers.cr0_names(), report.registers.efer_names(), report.registers.rflags_names()).unwrap();
+    for flag in ["PG", "PE", "LMA", "IF"] {
+        if !names.contains(flag) {
+            fail(format_args!("flag {} not decoded in '{}'", flag, names.as_str()));
+        }
+    }
+
+    let (title, title_color) = ScreenLine::read(0);
+    let (_, body_color) = ScreenLine::read(10);
+    // titre rouge sur blanc, texte blanc sur rouge
+    if !title.contains("KERNEL CRASH") || title_color != 0xf4 || body_color != 0x4f {
+        fail(format_args!("no crash page on the screen (colors {:#x}, {:#x})", title_color, body_color));
+    }
+    if !(2..24).any(|row| ScreenLine::read(row).0.contains("INVALID OPCODE")) {
+        fail(format_args!("the exception is not named on the crash page"));
+    }
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// The decoded flag names, gathered in a fixed buffer.
+struct Names {
+    bytes: [u8; 128],
+    len: usize,
+}
+
+impl Names {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+
+    fn contains(&self, flag: &str) -> bool {
+        self.as_str().split(' ').any(|name| name == flag)
+    }
+}
+
+impl Write for Names {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = (self.len + s.len()).min(self.bytes.len());
+        self.bytes[self.len..end].copy_from_slice(&s.as_bytes()[..end - self.len]);
+        self.len = end;
+        Ok(())
+    }
+}
+
+fn fail(message: core::fmt::Arguments) -> ! {
+    serial_println!("[failed]");
+    serial_println!("{}", message);
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
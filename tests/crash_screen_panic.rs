@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+//Une panique du noyau doit passer par crash::panic et laisser la meme page de plantage qu'une exception fatale.
+
+use blog_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+const MESSAGE: &str = "crash page test panic";
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    //cargo test --test crash_screen_panic
+    serial_print!("crash_screen_panic::panic_shows_crash_page...\t");
+
+    blog_os::init();
+    blog_os::crash::set_panic_hook(check_screen);
+
+    panic!("{}", MESSAGE);
+}
+
+/// A line of the VGA text buffer, without its colors.
+struct ScreenLine([u8; 80]);
+
+impl ScreenLine {
+    fn read(row: usize) -> (Self, u8) {
+        let buffer = 0xb8000 as *const u16;
+        let mut line = [0; 80];
+        for (col, byte) in line.iter_mut().enumerate() {
+            *byte = unsafe { buffer.add(row * 80 + col).read_volatile() } as u8;
+        }
+        let color = (unsafe { buffer.add(row * 80).read_volatile() } >> 8) as u8;
+        (ScreenLine(line), color)
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        self.0.windows(text.len()).any(|window| window == text.as_bytes())
+    }
+}
+
+fn check_screen(_info: &PanicInfo) {
+    let (title, title_color) = ScreenLine::read(0);
+    let (_, body_color) = ScreenLine::read(10);
+    // titre rouge sur blanc, texte blanc sur rouge
+    if !title.contains("KERNEL PANIC") || title_color != 0xf4 || body_color != 0x4f {
+        fail(format_args!("no crash page on the screen (colors {:#x}, {:#x})", title_color, body_color));
+    }
+    if !(2..24).any(|row| ScreenLine::read(row).0.contains(MESSAGE)) {
+        fail(format_args!("the panic message is not on the crash page"));
+    }
+    if !(2..24).any(|row| ScreenLine::read(row).0.contains("RAX")) {
+        fail(format_args!("the registers are not on the crash page"));
+    }
+    if !ScreenLine::read(24).0.contains("The system has been halted.") {
+        fail(format_args!("the halt message is not on the last line"));
+    }
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+}
+
+fn fail(message: core::fmt::Arguments) -> ! {
+    serial_println!("[failed]");
+    serial_println!("{}", message);
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::crash::panic(info)
+}
@@ -0,0 +1,128 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use blog_os::allocator;
+use blog_os::memory::{
+    self,
+    vmm::{self, RegionKind},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test demand_paging
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+fn is_mapped(addr: VirtAddr) -> bool {
+    memory::with_kernel_memory(|memory| {
+        memory
+            .mapper
+            .translate_page(Page::<Size4KiB>::containing_address(addr))
+            .is_ok()
+    })
+    .unwrap()
+}
+
+//Seules les pages touchees d'une region paresseuse sont mappees, et elles arrivent remises a zero.
+#[test_case]
+fn lazy_pages_are_mapped_on_access() {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let region = vmm::with_vmm(|vmm| vmm.allocate_lazy(16 * 4096, RegionKind::Module, flags)).unwrap();
+    let third_page = region.start + 3 * 4096u64;
+    let fifth_page = region.start + 5 * 4096u64;
+    assert!(!is_mapped(third_page));
+
+    let faults = vmm::demand_faults();
+    let ptr: *mut u64 = third_page.as_mut_ptr();
+    unsafe { ptr.write_volatile(42) };
+    assert_eq!(unsafe { ptr.read_volatile() }, 42);
+    assert_eq!(vmm::demand_faults(), faults + 1);
+    assert!(is_mapped(third_page));
+    assert!(!is_mapped(fifth_page));
+
+    let ptr: *const u64 = fifth_page.as_ptr();
+    assert_eq!(unsafe { ptr.read_volatile() }, 0);
+    assert_eq!(vmm::demand_faults(), faults + 2);
+
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| {
+            unsafe {
+                vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                    .unwrap()
+            };
+            vmm.release(region.start).unwrap();
+        })
+    })
+    .unwrap();
+    assert!(!is_mapped(third_page));
+}
+
+//Une lecture dans une region paresseuse en lecture seule est valide : la page arrive remise a zero, sans ecriture par son
+//mapping.
+#[test_case]
+fn read_only_lazy_region_can_be_read() {
+    let flags = PageTableFlags::PRESENT;
+    let region = vmm::with_vmm(|vmm| vmm.allocate_lazy(4 * 4096, RegionKind::Module, flags)).unwrap();
+    let page = region.start + 4096u64;
+
+    let faults = vmm::demand_faults();
+    let ptr: *const u64 = page.as_ptr();
+    assert_eq!(unsafe { ptr.read_volatile() }, 0);
+    assert_eq!(vmm::demand_faults(), faults + 1);
+    assert!(is_mapped(page));
+
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| {
+            unsafe {
+                vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                    .unwrap()
+            };
+            vmm.release(region.start).unwrap();
+        })
+    })
+    .unwrap();
+}
+
+//Le tas n'est pas une region paresseuse : la page apres sa fin n'est mappee que quand il grandit.
+#[test_case]
+fn heap_region_is_mapped_explicitly() {
+    let heap_end = VirtAddr::new((allocator::heap_start() + allocator::heap_size()) as u64);
+    let region = vmm::with_vmm(|vmm| *vmm.find(heap_end).unwrap());
+    assert_eq!(region.kind, RegionKind::Heap);
+    assert!(!region.lazy);
+    assert!(!is_mapped(heap_end));
+
+    let size_before = allocator::heap_size();
+    let vec: Vec<u8> = vec![1; 2 * allocator::HEAP_GROWTH_STEP];
+    assert!(allocator::heap_size() > size_before);
+    assert!(is_mapped(heap_end));
+    assert!(vec.iter().all(|&byte| byte == 1));
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+//Une division par zero doit aboutir dans le gestionnaire #DE du noyau puis dans crash, et non dans une double faute.
+
+use blog_os::interrupts::exceptions::{self, ErrorCode, ExceptionReport};
+use blog_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    //cargo test --test divide_error
+    serial_print!("divide_error::division_by_zero_is_reported...\t");
+
+    blog_os::init();
+    exceptions::set_crash_hook(check_report);
+
+    // la division de Rust verifie le diviseur et panique : il faut executer div directement
+    unsafe {
+        core::arch::asm!(
+            "xor eax, eax",
+            "xor edx, edx",
+            "xor ecx, ecx",
+            "div ecx",
+            out("eax") _,
+            out("ecx") _,
+            out("edx") _,
+        );
+    }
+
+    serial_println!("[failed]");
+    serial_println!("execution continued after a division by zero");
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+fn check_report(report: &ExceptionReport) {
+    if report.vector == 0 && report.decoded_error_code() == ErrorCode::None && report.registers.rip != 0 {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("unexpected report:\n{}", report);
+        exit_qemu(QemuExitCode::Failed);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
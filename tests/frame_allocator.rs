@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::memory::BitmapFrameAllocator;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+//l'allocateur est partage entre les tests, qui ne recoivent pas de BootInfo
+static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test frame_allocator
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+fn with_allocator(f: impl FnOnce(&mut BitmapFrameAllocator)) {
+    f(FRAME_ALLOCATOR.lock().as_mut().unwrap())
+}
+
+#[test_case]
+fn counts_are_consistent() {
+    with_allocator(|allocator| {
+        assert!(allocator.total_frames() > 0);
+        assert!(allocator.used_frames() > 0); // the bitmap itself
+        assert_eq!(
+            allocator.used_frames() + allocator.free_frames(),
+            allocator.total_frames()
+        );
+    });
+}
+
+#[test_case]
+fn allocated_frames_are_distinct() {
+    with_allocator(|allocator| {
+        let used = allocator.used_frames();
+        let mut frames: [Option<PhysFrame>; 64] = [None; 64];
+        for slot in frames.iter_mut() {
+            *slot = allocator.allocate_frame();
+        }
+        assert_eq!(allocator.used_frames(), used + frames.len());
+        for (i, a) in frames.iter().enumerate() {
+            assert!(a.is_some());
+            for b in &frames[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        for frame in frames.iter() {
+            unsafe { allocator.deallocate_frame(frame.unwrap()) };
+        }
+        assert_eq!(allocator.used_frames(), used);
+    });
+}
+
+#[test_case]
+fn deallocated_frame_is_reused() {
+    with_allocator(|allocator| {
+        let frame: PhysFrame = allocator.allocate_frame().unwrap();
+        unsafe { allocator.deallocate_frame(frame) };
+        assert_eq!(allocator.allocate_frame(), Some(frame));
+        unsafe { allocator.deallocate_frame(frame) };
+    });
+}
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+//Charger dans DS un selecteur au-dela de la GDT provoque un #GP dont le code d'erreur est ce selecteur : le rapport doit le decoder.
+
+use blog_os::interrupts::exceptions::{self, DescriptorTable, ErrorCode, ExceptionReport, SelectorErrorCode};
+use blog_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+/// Index of a GDT entry far beyond the end of the kernel's GDT.
+const BAD_INDEX: u16 = 0x246;
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    //cargo test --test general_protection_fault
+    serial_print!("general_protection_fault::bad_selector_is_decoded...\t");
+
+    blog_os::init();
+    exceptions::set_crash_hook(check_report);
+
+    unsafe {
+        core::arch::asm!("mov ds, {0:x}", in(reg) BAD_INDEX << 3);
+    }
+
+    serial_println!("[failed]");
+    serial_println!("a selector outside the GDT was loaded");
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+fn check_report(report: &ExceptionReport) {
+    let expected = SelectorErrorCode {
+        external: false,
+        table: DescriptorTable::Gdt,
+        index: BAD_INDEX,
+    };
+    if report.vector == 13 && report.decoded_error_code() == ErrorCode::Selector(expected) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("unexpected report:\n{}", report);
+        exit_qemu(QemuExitCode::Failed);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
@@ -13,7 +13,7 @@ use core::panic::PanicInfo;
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
-use blog_os::allocator::HEAP_SIZE;
+use blog_os::allocator::{self, HEAP_SIZE};
 
 entry_point!(main);
 
@@ -22,18 +22,19 @@ entry_point!(main);
 // d'allocation et appelons test_mainsans condition.
 fn main(boot_info: &'static BootInfo) -> ! {
     //cargo test --test heap_allocation
-    use blog_os::allocator;
-    use blog_os::memory::{self, BootInfoFrameAllocator};
+    //pour un autre allocateur : cargo test --test heap_allocation --no-default-features --features alloc_linked_list
+    use blog_os::memory::{self, BitmapFrameAllocator};
     use x86_64::VirtAddr;
 
     blog_os::init();
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
     let mut frame_allocator = unsafe {
-        BootInfoFrameAllocator::init(&boot_info.memory_map)
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
     };
     allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
 
     test_main();
     loop {}
@@ -79,7 +80,7 @@ fn many_boxes() {
 //Comme le many_boxestest, ce test crée un grand nombre d'allocations pour provoquer une panne de mémoire insuffisante si l'allocateur ne réutilise pas la mémoire libérée. De plus, 
 //le test crée une long_livedallocation qui dure toute l'exécution de la boucle.
 
-//le test echoue logiquement si nous utilisons le bump alocator
+//avec le bump alocator, le test ne passe que parce que le tas peut grandir
 #[test_case]
 fn many_boxes_long_lived() {
     let long_lived = Box::new(1);
@@ -89,3 +90,33 @@ fn many_boxes_long_lived() {
     }
     assert_eq!(*long_lived, 1); 
 }
+
+//Une allocation plus grande que le tas initial oblige l'allocateur a agrandir le tas.
+#[test_case]
+fn heap_grows_on_demand() {
+    let size_before = allocator::heap_size();
+    let n = size_before; // size_before * size_of::<usize>() octets
+    let vec: Vec<usize> = (0..n).collect();
+    assert!(allocator::heap_size() > size_before);
+    assert_eq!(vec.iter().sum::<usize>(), (n - 1) * n / 2);
+}
+
+//Les statistiques du tas suivent chaque allocation et liberation.
+#[test_case]
+fn heap_stats_track_allocations() {
+    let before = allocator::heap_stats();
+    let value = Box::new([0u8; 100]);
+    let during = allocator::heap_stats();
+    assert_eq!(during.allocations, before.allocations + 1);
+    assert_eq!(during.bytes_in_use, before.bytes_in_use + 100);
+    assert!(during.peak_bytes_in_use >= during.bytes_in_use);
+    if cfg!(feature = "alloc_linked_list") {
+        let largest = during.largest_free_block.expect("largest free block unknown");
+        assert!(largest > 0 && largest <= during.heap_size);
+    }
+
+    drop(value);
+    let after = allocator::heap_stats();
+    assert_eq!(after.deallocations, before.deallocations + 1);
+    assert_eq!(after.bytes_in_use, before.bytes_in_use);
+}
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+#![reexport_test_harness_main = "test_main"]
+//Comme should_panic : le test reussit si DebugAllocator signale une double liberation, meme quand l'allocateur enveloppe a deja
+//reutilise l'en-tete du bloc libere. Il utilise son propre petit tas, il n'a donc pas besoin de la feature heap_debug.
+use blog_os::allocator::{
+    debug::DebugAllocator, linked_list::LinkedListAllocator, HeapAllocator, Locked,
+};
+use blog_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+
+#[repr(align(4096))]
+#[allow(dead_code)]
+struct Arena([u8; ARENA_SIZE]);
+
+const ARENA_SIZE: usize = 4096;
+static mut ARENA: Arena = Arena([0; ARENA_SIZE]);
+static ALLOCATOR: DebugAllocator<Locked<LinkedListAllocator>> =
+    DebugAllocator::new(Locked::new(LinkedListAllocator::new()));
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    unsafe {
+        ALLOCATOR
+            .inner()
+            .lock()
+            .init(ptr::addr_of_mut!(ARENA) as usize, ARENA_SIZE);
+    }
+    double_free_is_detected();
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+fn double_free_is_detected() {
+    serial_print!("heap_debug_double_free::double_free_is_detected...\t");
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = ALLOCATOR.alloc(layout);
+        let other = ALLOCATOR.alloc(layout);
+        assert!(!ptr.is_null() && !other.is_null());
+        ALLOCATOR.dealloc(ptr, layout);
+        // l'allocateur enveloppe redonne le debut du bloc et ecrit son noeud de liste juste apres, sur l'en-tete
+        let reused = ALLOCATOR.inner().alloc(Layout::from_size_align(32, 8).unwrap());
+        assert!(!reused.is_null());
+        ALLOCATOR.dealloc(ptr, layout);
+    }
+}
+
+/// Keeps the start of the panic message, to check which error was detected.
+struct MessageBuffer {
+    bytes: [u8; 128],
+    len: usize,
+}
+
+impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut message = MessageBuffer { bytes: [0; 128], len: 0 };
+    let _ = write!(message, "{}", info.message());
+    let message = core::str::from_utf8(&message.bytes[..message.len]).unwrap_or("");
+    if message.starts_with("heap debug: double free") {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]\n{}", info);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    blog_os::hlt_loop();
+}
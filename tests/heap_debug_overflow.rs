@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(test_runner)]
+#![reexport_test_harness_main = "test_main"]
+//Comme should_panic : le test reussit si DebugAllocator panique en trouvant la zone de garde ecrasee a la liberation.
+//Il utilise son propre petit tas, il n'a donc pas besoin de la feature heap_debug.
+use blog_os::allocator::{
+    debug::DebugAllocator, linked_list::LinkedListAllocator, HeapAllocator, Locked,
+};
+use blog_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use core::alloc::{GlobalAlloc, Layout};
+use core::panic::PanicInfo;
+use core::ptr;
+
+#[repr(align(4096))]
+#[allow(dead_code)]
+struct Arena([u8; ARENA_SIZE]);
+
+const ARENA_SIZE: usize = 4096;
+static mut ARENA: Arena = Arena([0; ARENA_SIZE]);
+static ALLOCATOR: DebugAllocator<Locked<LinkedListAllocator>> =
+    DebugAllocator::new(Locked::new(LinkedListAllocator::new()));
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    unsafe {
+        ALLOCATOR
+            .inner()
+            .lock()
+            .init(ptr::addr_of_mut!(ARENA) as usize, ARENA_SIZE);
+    }
+    overflow_is_detected();
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+fn overflow_is_detected() {
+    serial_print!("heap_debug_overflow::overflow_is_detected...\t");
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = ALLOCATOR.alloc(layout);
+        assert!(!ptr.is_null());
+        // un octet de trop, comme une boucle qui va jusqu'a <= len
+        ptr.add(layout.size()).write(0);
+        ALLOCATOR.dealloc(ptr, layout);
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    blog_os::hlt_loop();
+}
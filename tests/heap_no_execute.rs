@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+//Les pages du tas sont mappees avec NO_EXECUTE : sauter sur du code copie dans le tas doit provoquer une page fault
+//d'execution (INSTRUCTION_FETCH) sur une page presente, et non executer l'instruction.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use blog_os::memory::{self, BitmapFrameAllocator};
+use blog_os::{allocator, exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+//adresse du code copie dans le tas, comparee a l'adresse de la faute
+static CODE_ADDR: AtomicU64 = AtomicU64::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test heap_no_execute
+    serial_print!("heap_no_execute::executing_heap_faults...\t");
+
+    blog_os::gdt::init();
+    init_test_idt();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    // 0xc3 : ret
+    let code = Box::new([0xc3u8; 16]);
+    CODE_ADDR.store(code.as_ptr() as u64, Ordering::SeqCst);
+    let function: extern "C" fn() = unsafe { core::mem::transmute(code.as_ptr()) };
+    function();
+
+    serial_println!("[failed]");
+    serial_println!("code on the heap was executed");
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    use x86_64::registers::control::Cr2;
+
+    let expected = PageFaultErrorCode::INSTRUCTION_FETCH | PageFaultErrorCode::PROTECTION_VIOLATION;
+    if error_code.contains(expected) && Cr2::read().as_u64() == CODE_ADDR.load(Ordering::SeqCst) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("fault at {:?}, error code: {:?}", Cr2::read(), error_code);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
@@ -0,0 +1,130 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::allocator;
+use blog_os::memory::{
+    self,
+    vmm::{self, RegionKind},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::structures::paging::{
+    mapper::{MappedFrame, TranslateResult},
+    FrameAllocator, FrameDeallocator, PageSize, PageTableFlags, PhysFrame, Size2MiB, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test huge_pages
+    blog_os::init();
+    PHYSICAL_MEMORY_OFFSET.store(boot_info.physical_memory_offset, Ordering::Relaxed);
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+const FLAGS: PageTableFlags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+
+fn is_huge(addr: VirtAddr) -> bool {
+    memory::with_kernel_memory(|memory| {
+        matches!(
+            memory.mapper.translate(addr),
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size2MiB(_),
+                ..
+            }
+        )
+    })
+    .unwrap()
+}
+
+#[test_case]
+fn bitmap_allocates_aligned_huge_frames() {
+    memory::with_kernel_memory(|memory| {
+        let allocator = &mut memory.frame_allocator;
+        let used = allocator.used_frames();
+        let frame: PhysFrame<Size2MiB> = allocator.allocate_frame().expect("no free 2 MiB frame");
+        assert!(frame.start_address().is_aligned(Size2MiB::SIZE));
+        assert_eq!(allocator.used_frames(), used + 512);
+        unsafe { allocator.deallocate_frame(frame) };
+        assert_eq!(allocator.used_frames(), used);
+    })
+    .unwrap();
+}
+
+//Une region de 4 MiB est alignee sur 2 MiB et mappee avec deux grandes pages.
+#[test_case]
+fn large_region_uses_huge_pages() {
+    memory::with_kernel_memory(|memory| {
+        let free_frames = memory.frame_allocator.free_frames();
+        let region = vmm::with_vmm(|vmm| {
+            let region = vmm.allocate(2 * Size2MiB::SIZE, RegionKind::Module, FLAGS).unwrap();
+            vmm.map_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap()
+        });
+        assert!(region.start.is_aligned(Size2MiB::SIZE));
+
+        let ptr: *mut u64 = (region.start + Size2MiB::SIZE + 8u64).as_mut_ptr();
+        unsafe { ptr.write_volatile(0xdead_beef) };
+        assert_eq!(unsafe { ptr.read_volatile() }, 0xdead_beef);
+
+        vmm::with_vmm(|vmm| unsafe {
+            vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap();
+            vmm.release(region.start).unwrap();
+        });
+        // seules les tables de pages intermediaires restent allouees
+        assert!(memory.frame_allocator.free_frames() + 3 >= free_frames);
+    })
+    .unwrap();
+}
+
+//Les 2 premiers MiB de memoire physique (qui contiennent le tampon VGA) vus a travers une region MMIO.
+#[test_case]
+fn physical_range_is_mapped_with_huge_pages() {
+    let region = memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| unsafe {
+            let region = vmm.allocate(Size2MiB::SIZE, RegionKind::Mmio, FLAGS).unwrap();
+            vmm.map_region_to(region.start, PhysAddr::new(0), &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap()
+        })
+    })
+    .unwrap();
+    assert!(is_huge(region.start));
+
+    let offset = PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed);
+    let through_region = (region.start + 0xb8000u64).as_ptr::<u16>();
+    let through_offset = (offset + 0xb8000) as *const u16;
+    assert_eq!(unsafe { through_region.read_volatile() }, unsafe { through_offset.read_volatile() });
+
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| unsafe {
+            vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap();
+            vmm.release(region.start).unwrap();
+        })
+    })
+    .unwrap();
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+//ud2 doit aboutir dans le gestionnaire #UD du noyau, avec l'adresse de l'instruction dans le rapport.
+
+use blog_os::interrupts::exceptions::{self, exception_name, ExceptionReport};
+use blog_os::{exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+entry_point!(main);
+
+static UD2_ADDR: AtomicU64 = AtomicU64::new(0);
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    //cargo test --test invalid_opcode
+    serial_print!("invalid_opcode::ud2_is_reported...\t");
+
+    blog_os::init();
+    exceptions::set_crash_hook(check_report);
+
+    // l'adresse de ud2 est enregistree juste avant de l'executer
+    unsafe {
+        core::arch::asm!(
+            "lea {addr}, [rip + 2f]",
+            "mov [{slot}], {addr}",
+            "2:",
+            "ud2",
+            slot = in(reg) UD2_ADDR.as_ptr(),
+            addr = out(reg) _,
+        );
+    }
+
+    serial_println!("[failed]");
+    serial_println!("execution continued after ud2");
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+fn check_report(report: &ExceptionReport) {
+    let expected = UD2_ADDR.load(Ordering::SeqCst);
+    if report.vector == 6
+        && exception_name(6).0 == "#UD"
+        && report.error_code.is_none()
+        && report.registers.rip == expected
+    {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("unexpected report (ud2 at {:#x}):\n{}", expected, report);
+        exit_qemu(QemuExitCode::Failed);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
@@ -0,0 +1,163 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::allocator;
+use blog_os::interrupts::{self, irq, InterruptController};
+use blog_os::memory::{self, BitmapFrameAllocator};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use x86_64::instructions::port::Port;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test irq
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+/// Waits for `count` more timer interrupts.
+fn wait_for_ticks(count: u64) {
+    let target = interrupts::timer_ticks() + count;
+    while interrupts::timer_ticks() < target {
+        x86_64::instructions::hlt();
+    }
+}
+
+static FUNCTION_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_call() {
+    FUNCTION_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test_case]
+fn shared_timer_line_calls_every_handler() {
+    let handler = irq::register(0, irq::Sharing::Shared, count_call).expect("register failed");
+    wait_for_ticks(3);
+    assert!(FUNCTION_CALLS.load(Ordering::Relaxed) >= 3);
+
+    irq::unregister(handler).unwrap();
+    let calls = FUNCTION_CALLS.load(Ordering::Relaxed);
+    wait_for_ticks(2);
+    assert_eq!(FUNCTION_CALLS.load(Ordering::Relaxed), calls);
+    assert_eq!(irq::unregister(handler), Err(irq::IrqError::NoSuchHandler));
+}
+
+#[test_case]
+fn closures_can_be_registered() {
+    static CLOSURE_CALLS: AtomicUsize = AtomicUsize::new(0);
+    let step = 2;
+    let handler = irq::register_closure(0, irq::Sharing::Shared, move || {
+        CLOSURE_CALLS.fetch_add(step, Ordering::Relaxed);
+    })
+    .expect("register failed");
+    wait_for_ticks(2);
+    assert!(CLOSURE_CALLS.load(Ordering::Relaxed) >= 4);
+    irq::unregister(handler).unwrap();
+}
+
+static IN_HANDLER_CHECKS: AtomicUsize = AtomicUsize::new(0);
+
+//dispatch relache le verrou des lignes avant d'appeler les gestionnaires : ces appels ne doivent pas bloquer
+fn use_irq_api() {
+    if irq::has_handlers(0)
+        && irq::unmask(0).is_ok()
+        && irq::register(0, irq::Sharing::Shared, count_call) == Err(irq::IrqError::InHandler)
+    {
+        IN_HANDLER_CHECKS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test_case]
+fn handlers_can_use_the_irq_api() {
+    let handler = irq::register(0, irq::Sharing::Shared, use_irq_api).expect("register failed");
+    wait_for_ticks(2);
+    assert!(IN_HANDLER_CHECKS.load(Ordering::Relaxed) >= 2);
+    irq::unregister(handler).unwrap();
+}
+
+#[test_case]
+fn exclusive_lines_are_not_shared() {
+    // le clavier est enregistre en exclusif, le minuteur en partage
+    assert_eq!(
+        irq::register(1, irq::Sharing::Shared, count_call),
+        Err(irq::IrqError::Busy(1))
+    );
+    assert_eq!(
+        irq::register(0, irq::Sharing::Exclusive, count_call),
+        Err(irq::IrqError::Busy(0))
+    );
+}
+
+#[test_case]
+fn lines_are_checked() {
+    assert_eq!(
+        irq::register(irq::MAX_IRQS as u8, irq::Sharing::Shared, count_call),
+        Err(irq::IrqError::NoSuchLine(irq::MAX_IRQS as u8))
+    );
+    // les PIC ne connaissent que 16 lignes
+    assert_eq!(
+        irq::register(20, irq::Sharing::Shared, count_call),
+        Err(irq::IrqError::NotRoutable(20))
+    );
+}
+
+#[test_case]
+fn unused_lines_are_masked_on_the_pic() {
+    let mask = unsafe { Port::<u8>::new(0x21).read() };
+    // minuteur, clavier et cascade demasques, le reste masque
+    assert_eq!(mask & 0b111, 0);
+    assert_eq!(mask | 0b111, 0xff);
+
+    let handler = irq::register(3, irq::Sharing::Shared, count_call).unwrap();
+    assert_eq!(unsafe { Port::<u8>::new(0x21).read() } & (1 << 3), 0);
+    irq::unregister(handler).unwrap();
+    assert_ne!(unsafe { Port::<u8>::new(0x21).read() } & (1 << 3), 0);
+}
+
+#[test_case]
+fn masked_line_stays_masked() {
+    irq::mask(0).unwrap();
+    assert_ne!(unsafe { Port::<u8>::new(0x21).read() } & 1, 0);
+    irq::unmask(0).unwrap();
+    wait_for_ticks(2);
+}
+
+#[test_case]
+fn handlers_follow_the_switch_to_the_apic() {
+    static APIC_CALLS: AtomicUsize = AtomicUsize::new(0);
+    let handler = irq::register_closure(0, irq::Sharing::Shared, || {
+        APIC_CALLS.fetch_add(1, Ordering::Relaxed);
+    })
+    .unwrap();
+    assert_eq!(interrupts::select_controller(InterruptController::Apic), InterruptController::Apic);
+    wait_for_ticks(3);
+    assert!(APIC_CALLS.load(Ordering::Relaxed) >= 3);
+    irq::unregister(handler).unwrap();
+
+    // les lignes au-dela de 15 sont des GSI de l'I/O APIC
+    let high = irq::register(20, irq::Sharing::Shared, count_call).expect("GSI 20 not routable");
+    assert!(irq::has_handlers(20));
+    irq::unregister(high).unwrap();
+    assert!(!irq::has_handlers(20));
+}
@@ -0,0 +1,106 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::allocator;
+use blog_os::memory::{
+    self,
+    walk::{self, Translation},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+//debut du mappage de la memoire physique, pour retrouver l'alias de .text
+static PHYS_MEM_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test kernel_protection
+    blog_os::init();
+    PHYS_MEM_OFFSET.store(boot_info.physical_memory_offset, Ordering::Relaxed);
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::protect::remap_kernel(&mut mapper, &boot_info.memory_map) }
+        .expect("failed to remap the kernel sections");
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+static READ_ONLY: [u8; 4096] = [1; 4096];
+static WRITABLE: AtomicU64 = AtomicU64::new(0);
+
+fn flags_of(addr: VirtAddr) -> PageTableFlags {
+    match walk::translate(addr) {
+        Translation::Mapped { flags, .. } => flags,
+        translation => panic!("{:?} translated to {:?}", addr, translation),
+    }
+}
+
+fn text_addr() -> VirtAddr {
+    let function: fn() -> VirtAddr = text_addr;
+    VirtAddr::new(function as usize as u64)
+}
+
+#[test_case]
+fn text_is_executable_and_read_only() {
+    let flags = flags_of(text_addr());
+    assert!(!flags.contains(PageTableFlags::WRITABLE));
+    assert!(!flags.contains(PageTableFlags::NO_EXECUTE));
+}
+
+#[test_case]
+fn rodata_is_read_only_and_not_executable() {
+    let flags = flags_of(VirtAddr::from_ptr(&READ_ONLY));
+    assert!(!flags.contains(PageTableFlags::WRITABLE));
+    assert!(flags.contains(PageTableFlags::NO_EXECUTE));
+}
+
+#[test_case]
+fn data_is_writable_and_not_executable() {
+    WRITABLE.fetch_add(1, Ordering::Relaxed);
+    let flags = flags_of(VirtAddr::from_ptr(&WRITABLE));
+    assert!(flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+}
+
+#[test_case]
+fn heap_is_not_executable() {
+    let flags = flags_of(VirtAddr::new(allocator::heap_start() as u64));
+    assert!(flags.contains(PageTableFlags::NO_EXECUTE));
+}
+
+#[test_case]
+fn physical_memory_alias_of_text_is_not_executable() {
+    let phys = match walk::translate(text_addr()) {
+        Translation::Mapped { phys, .. } => phys,
+        translation => panic!("text translated to {:?}", translation),
+    };
+    let alias = VirtAddr::new(PHYS_MEM_OFFSET.load(Ordering::Relaxed) + phys.as_u64());
+    let flags = walk::effective_flags(alias).expect("physical memory alias is not mapped");
+    assert!(flags.contains(PageTableFlags::NO_EXECUTE));
+}
+
+#[test_case]
+fn boot_stack_is_not_executable() {
+    let local = 0u64;
+    let flags = walk::effective_flags(VirtAddr::from_ptr(&local)).unwrap();
+    assert!(flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+}
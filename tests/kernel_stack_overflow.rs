@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+//Comme stack_overflow, mais sur une pile allouee par memory::stack : le debordement doit toucher la page de garde. La page fault
+//ne peut pas empiler sa trame sur la pile pleine, le CPU leve donc une double fault (sur sa pile IST), qui doit indiquer quelle
+//pile a deborde : CR2 garde l'adresse de la page fault.
+
+use blog_os::memory::{self, stack::KernelStack, BitmapFrameAllocator};
+use blog_os::{allocator, exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test kernel_stack_overflow
+    serial_print!("kernel_stack_overflow::overflow_hits_guard_page...\t");
+
+    blog_os::gdt::init();
+    init_test_idt();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    let stack = KernelStack::new(4 * 4096, "test stack").expect("stack allocation failed");
+    unsafe {
+        core::arch::asm!(
+            "mov rsp, {top}",
+            "call {entry}",
+            top = in(reg) stack.top().as_u64(),
+            entry = sym on_test_stack,
+            options(noreturn)
+        )
+    }
+}
+
+extern "C" fn on_test_stack() -> ! {
+    stack_overflow();
+    panic!("Execution continued after stack overflow");
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow(); // for each recursion, the return address is pushed
+    volatile::Volatile::new(0).read(); // prevent tail recursion optimizations
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(test_double_fault_handler)
+                .set_stack_index(blog_os::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+        idt
+    };
+}
+
+fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_double_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    use x86_64::registers::control::Cr2;
+
+    match memory::stack::overflowed_stack(Cr2::read()) {
+        Some("test stack") => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!("[failed]");
+            serial_println!("fault at {:?}, overflowed stack: {:?}", Cr2::read(), other);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
@@ -0,0 +1,132 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::memory::{self, report::MemoryReport, BitmapFrameAllocator};
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PageTable, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+static MEMORY_MAP: spin::Mutex<Option<&'static MemoryMap>> = spin::Mutex::new(None);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test memory_report
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    memory::init_kernel_memory(mapper, frame_allocator);
+    *MEMORY_MAP.lock() = Some(&boot_info.memory_map);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+fn memory_map() -> &'static MemoryMap {
+    MEMORY_MAP.lock().unwrap()
+}
+
+#[test_case]
+fn totals_match_the_memory_map() {
+    let report = MemoryReport::new(memory_map());
+    let total: u64 = memory_map()
+        .iter()
+        .map(|r| r.range.end_addr() - r.range.start_addr())
+        .sum();
+    assert_eq!(report.total_bytes(), total);
+
+    let usable = report.summary(MemoryRegionType::Usable);
+    assert!(usable.regions > 0);
+    assert!(usable.lowest < usable.highest);
+    let frames = memory::with_kernel_memory(|memory| memory.frame_allocator.total_frames()).unwrap();
+    assert_eq!(usable.bytes, frames as u64 * 4096);
+
+    // le noyau est toujours decrit par la carte memoire
+    assert!(report.bytes(MemoryRegionType::Kernel) > 0);
+}
+
+#[test_case]
+fn report_is_printable() {
+    blog_os::serial_println!("{}", MemoryReport::new(memory_map()));
+}
+
+//Les tables de pages du bootloader sont toujours actives : apres la recuperation, aucune ne doit sortir de l'allocateur.
+//Toutes les frames libres sont allouees puis chainees entre elles (l'adresse de la suivante au debut de chacune) pour les rendre.
+#[test_case]
+fn reclaim_adds_boot_memory_to_the_allocator() {
+    let report = MemoryReport::new(memory_map());
+    let tables = TableFrames::active();
+    let offset = memory::physical_memory_offset().unwrap();
+    memory::with_kernel_memory(|memory| {
+        let allocator = &mut memory.frame_allocator;
+        let (total, free) = (allocator.total_frames(), allocator.free_frames());
+        let reclaimed = unsafe { memory::report::reclaim_boot_memory(memory_map(), allocator) };
+        assert!(reclaimed as u64 * 4096 <= report.reclaimable_bytes());
+        assert_eq!(allocator.total_frames(), total + reclaimed);
+        assert_eq!(allocator.free_frames(), free + reclaimed);
+
+        let mut chain = 0u64;
+        while let Some(frame) = FrameAllocator::<Size4KiB>::allocate_frame(allocator) {
+            assert!(!tables.contains(frame), "page table frame {:?} handed out", frame);
+            let addr = frame.start_address().as_u64();
+            unsafe { (offset + addr).as_mut_ptr::<u64>().write(chain) };
+            chain = addr;
+        }
+        while chain != 0 {
+            let next = unsafe { (offset + chain).as_ptr::<u64>().read() };
+            unsafe { allocator.deallocate_frame(PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(chain))) };
+            chain = next;
+        }
+        assert_eq!(allocator.free_frames(), free + reclaimed);
+    })
+    .unwrap();
+}
+
+/// The frames of the active page tables, levels 4 to 1.
+struct TableFrames {
+    frames: [u64; 1024],
+    len: usize,
+}
+
+impl TableFrames {
+    fn active() -> Self {
+        let mut tables = TableFrames { frames: [0; 1024], len: 0 };
+        tables.add(Cr3::read().0.start_address().as_u64(), 4);
+        tables
+    }
+
+    fn add(&mut self, table: u64, level: u8) {
+        assert!(self.len < self.frames.len(), "too many page tables");
+        self.frames[self.len] = table;
+        self.len += 1;
+        if level == 1 {
+            return;
+        }
+        let offset = memory::physical_memory_offset().unwrap();
+        let entries: &PageTable = unsafe { &*(offset + table).as_ptr() };
+        for entry in entries.iter() {
+            let flags = entry.flags();
+            if flags.contains(PageTableFlags::PRESENT) && !flags.contains(PageTableFlags::HUGE_PAGE) {
+                self.add(entry.addr().as_u64(), level - 1);
+            }
+        }
+    }
+
+    fn contains(&self, frame: PhysFrame) -> bool {
+        self.frames[..self.len].contains(&frame.start_address().as_u64())
+    }
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+//Tests de la gestion du manque de memoire : le tas est limite a sa taille actuelle, puis on verifie que les fonctions de
+//recuperation sont appelees et que les fonctions try_* echouent proprement.
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+
+use alloc::{format, vec::Vec};
+use core::alloc::Layout;
+use blog_os::allocator::{self, oom};
+use spin::Mutex;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test out_of_memory
+    use blog_os::memory::{self, BitmapFrameAllocator};
+    use x86_64::VirtAddr;
+
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    // le tas ne peut plus grandir
+    allocator::set_heap_limit(allocator::heap_size());
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+/// Memory held only so that the reclaim callback has something to free.
+static BALLAST: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+fn drop_ballast(_needed: usize) -> usize {
+    match BALLAST.lock().take() {
+        Some(ballast) => ballast.capacity(),
+        None => 0,
+    }
+}
+
+//Une allocation qui ne tient que si le lest est libere reussit grace a la fonction de recuperation.
+#[test_case]
+fn reclaimer_frees_memory() {
+    let size = allocator::heap_size() / 2;
+    *BALLAST.lock() = Some(Vec::with_capacity(size));
+    oom::register_reclaimer(drop_ballast).unwrap();
+
+    let vec: Vec<u8> = Vec::with_capacity(size);
+    assert!(BALLAST.lock().is_none());
+    assert_eq!(vec.capacity(), size);
+}
+
+#[test_case]
+fn try_allocation_fails_gracefully() {
+    let size = allocator::heap_limit() * 2;
+    let error = oom::try_vec_with_capacity::<u8>(size).unwrap_err();
+    assert_eq!(error.size, size);
+
+    // le tas reste utilisable apres l'echec
+    let value = oom::try_box(42u64).unwrap();
+    assert_eq!(*value, 42);
+}
+
+//Le rapport donne le plus grand bloc libre reel : une allocation de cette taille reussit encore, le tas ne pouvant plus grandir.
+#[test_case]
+fn report_gives_the_largest_free_block() {
+    let layout = Layout::from_size_align(allocator::heap_limit() * 2, 8).unwrap();
+    let report = oom::OomReport::new(layout);
+    assert_eq!(report.heap_limit, allocator::heap_limit());
+    assert!(format!("{}", report).starts_with("OUT OF MEMORY: failed to allocate"));
+    if !cfg!(feature = "alloc_linked_list") {
+        return;
+    }
+
+    let largest = report.stats.largest_free_block.expect("largest free block unknown");
+    assert!(largest > 0 && largest < allocator::heap_size());
+    let block = oom::try_vec_with_capacity::<u8>(largest).unwrap();
+    assert!(oom::try_vec_with_capacity::<u8>(largest + 1).is_err());
+    drop(block);
+    assert!(format!("{}", report).contains(&format!("largest free block: {} bytes", largest)));
+}
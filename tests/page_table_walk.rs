@@ -0,0 +1,137 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::allocator;
+use blog_os::memory::{
+    self,
+    vmm::{self, RegionKind},
+    walk::{self, MappingSize, Translation},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::structures::paging::{PageSize, PageTableFlags, Size2MiB, Translate};
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test page_table_walk
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+const FLAGS: PageTableFlags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+
+#[test_case]
+fn translate_agrees_with_mapper() {
+    let heap = VirtAddr::new(allocator::heap_start() as u64 + 0x123);
+    let expected = memory::with_kernel_memory(|memory| memory.mapper.translate_addr(heap)).unwrap();
+    match walk::translate(heap) {
+        Translation::Mapped {
+            phys,
+            page_size,
+            level,
+            flags,
+        } => {
+            assert_eq!(Some(phys), expected);
+            assert_eq!(page_size, MappingSize::Page4KiB);
+            assert_eq!(level, 1);
+            assert!(flags.contains(FLAGS));
+        }
+        translation => panic!("heap translated to {:?}", translation),
+    }
+}
+
+//Une region reservee n'est jamais mappee : la recherche s'arrete au plus tard sur l'entree de la table de niveau 1.
+#[test_case]
+fn translate_reports_missing_level() {
+    let region = vmm::with_vmm(|vmm| vmm.allocate(4096, RegionKind::Reserved, FLAGS)).unwrap();
+    match walk::translate(region.start) {
+        Translation::NotMapped { level } => assert!((1..=4).contains(&level)),
+        translation => panic!("reserved region translated to {:?}", translation),
+    }
+    vmm::with_vmm(|vmm| vmm.release(region.start)).unwrap();
+}
+
+#[test_case]
+fn mappings_are_sorted_and_merged() {
+    let mut previous: Option<walk::MappedRange> = None;
+    for range in walk::mappings() {
+        assert!(range.size > 0 && range.size % range.page_size.bytes() == 0);
+        if let Some(previous) = previous {
+            assert!(previous.end() <= range.virt);
+            // deux plages qui se suivent auraient du etre fusionnees
+            assert!(
+                previous.end() != range.virt
+                    || previous.phys + previous.size != range.phys
+                    || previous.flags != range.flags
+                    || previous.page_size != range.page_size
+            );
+        }
+        previous = Some(range);
+    }
+    assert!(previous.is_some());
+
+    let heap = VirtAddr::new(allocator::heap_start() as u64);
+    let heap_range = walk::mappings()
+        .find(|range| range.contains(heap))
+        .expect("heap not found in the mappings");
+    assert!(heap_range.flags.contains(FLAGS));
+}
+
+#[test_case]
+fn huge_mapping_is_listed() {
+    let region = memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| unsafe {
+            let region = vmm.allocate(Size2MiB::SIZE, RegionKind::Mmio, FLAGS).unwrap();
+            vmm.map_region_to(region.start, PhysAddr::new(0), &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap()
+        })
+    })
+    .unwrap();
+
+    let range = walk::mappings()
+        .find(|range| range.contains(region.start))
+        .expect("region not found in the mappings");
+    assert_eq!(range.page_size, MappingSize::Page2MiB);
+    assert_eq!(range.virt, region.start);
+    assert_eq!(range.phys, PhysAddr::new(0));
+    assert!(range.flags.contains(PageTableFlags::HUGE_PAGE));
+    assert!(matches!(
+        walk::translate(region.start + 0xb8000u64),
+        Translation::Mapped { phys, level: 2, .. } if phys == PhysAddr::new(0xb8000)
+    ));
+
+    memory::with_kernel_memory(|memory| {
+        vmm::with_vmm(|vmm| unsafe {
+            vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap();
+            vmm.release(region.start).unwrap();
+        })
+    })
+    .unwrap();
+}
+
+#[test_case]
+fn print_mappings_does_not_panic() {
+    walk::print_mappings();
+}
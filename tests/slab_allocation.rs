@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use blog_os::allocator::slab::SlabCache;
+use bootloader::{entry_point, BootInfo};
+use core::mem;
+use core::panic::PanicInfo;
+use core::ptr::NonNull;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test slab_allocation
+    use blog_os::allocator;
+    use blog_os::memory::{self, BitmapFrameAllocator};
+    use x86_64::VirtAddr;
+
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+//un objet de la taille d'une petite structure du noyau
+#[derive(Debug, PartialEq)]
+struct Object {
+    id: u64,
+    data: [u64; 5],
+}
+
+#[test_case]
+fn objects_are_distinct_and_aligned() {
+    let mut cache = SlabCache::<Object>::new();
+    let count = 3 * SlabCache::<Object>::objects_per_slab();
+    let objects: Vec<NonNull<Object>> = (0..count)
+        .map(|id| cache.alloc(Object { id: id as u64, data: [id as u64; 5] }).unwrap())
+        .collect();
+
+    for (id, object) in objects.iter().enumerate() {
+        assert_eq!(object.as_ptr() as usize % mem::align_of::<Object>(), 0);
+        assert_eq!(unsafe { object.as_ref() }.id, id as u64);
+    }
+    assert_eq!(cache.allocated(), count);
+    assert_eq!(cache.slab_counts(), (0, 3, 0));
+
+    for object in objects {
+        unsafe { cache.free(object) };
+    }
+    assert_eq!(cache.allocated(), 0);
+}
+
+#[test_case]
+fn empty_slabs_are_released() {
+    let mut cache = SlabCache::<Object>::new();
+    let count = 4 * SlabCache::<Object>::objects_per_slab();
+    let objects: Vec<NonNull<Object>> = (0..count)
+        .map(|id| cache.alloc(Object { id: id as u64, data: [0; 5] }).unwrap())
+        .collect();
+    for object in objects {
+        unsafe { cache.free(object) };
+    }
+
+    // at most one empty slab is kept for the next allocation
+    let (partial, full, empty) = cache.slab_counts();
+    assert_eq!((partial, full), (0, 0));
+    assert!(empty <= 1);
+    cache.shrink();
+    assert_eq!(cache.slab_counts(), (0, 0, 0));
+}
+
+#[test_case]
+fn freed_object_is_reused() {
+    let mut cache = SlabCache::<Object>::new();
+    let a = cache.alloc(Object { id: 1, data: [1; 5] }).unwrap();
+    let _b = cache.alloc(Object { id: 2, data: [2; 5] }).unwrap();
+    unsafe { cache.free(a) };
+    let c = cache.alloc(Object { id: 3, data: [3; 5] }).unwrap();
+    assert_eq!(a, c);
+    assert_eq!(unsafe { c.as_ref() }.id, 3);
+}
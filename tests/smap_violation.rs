@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+//Avec SMAP, une lecture d'une page utilisateur par le noyau en dehors de with_user_access doit provoquer une page fault
+//de protection, alors que la meme lecture dans with_user_access reussit.
+
+use blog_os::memory::{self, walk, BitmapFrameAllocator};
+use blog_os::{allocator, cpu, exit_qemu, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+static USER_ADDR: AtomicU64 = AtomicU64::new(0);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test smap_violation
+    serial_print!("smap_violation::kernel_read_of_user_page_faults...\t");
+
+    blog_os::gdt::init();
+    init_test_idt();
+    if !cpu::enable_protections().smap {
+        // rien a verifier sur un processeur sans SMAP
+        serial_println!("[skipped: the CPU does not support SMAP]");
+        exit_qemu(QemuExitCode::Success);
+    }
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    // une entree de niveau 4 libre, pour que les tables intermediaires soient accessibles en mode utilisateur
+    let index = (1..256u64)
+        .find(|index| walk::translate(VirtAddr::new(index << 39)) == walk::Translation::NotMapped { level: 4 })
+        .expect("no free level 4 entry in the lower half");
+    let page: Page = Page::containing_address(VirtAddr::new(index << 39));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let frame = FrameAllocator::<Size4KiB>::allocate_frame(&mut frame_allocator).unwrap();
+    unsafe { mapper.map_to(page, frame, flags, &mut frame_allocator).unwrap().flush() };
+
+    let ptr: *mut u64 = page.start_address().as_mut_ptr();
+    cpu::with_user_access(|| unsafe { ptr.write_volatile(42) });
+    USER_ADDR.store(ptr as u64, Ordering::SeqCst);
+    let value = unsafe { ptr.read_volatile() };
+
+    serial_println!("[failed]");
+    serial_println!("read {} from a user page without a page fault", value);
+    exit_qemu(QemuExitCode::Failed);
+    blog_os::hlt_loop();
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    use x86_64::registers::control::Cr2;
+
+    let user_addr = USER_ADDR.load(Ordering::SeqCst);
+    if user_addr != 0
+        && Cr2::read().as_u64() == user_addr
+        && error_code == PageFaultErrorCode::PROTECTION_VIOLATION
+    {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("fault at {:?}, error code: {:?}", Cr2::read(), error_code);
+        exit_qemu(QemuExitCode::Failed);
+    }
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::allocator;
+use blog_os::cpu;
+use blog_os::memory::{
+    self,
+    user::{copy_from_user, copy_to_user, UserCopyError},
+    walk::{self, Translation},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test user_access
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+const USER_RW: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::USER_ACCESSIBLE);
+
+//Les tables intermediaires creees par map_to ne sont accessibles en mode utilisateur que si elles sont nouvelles : on prend une
+//entree de niveau 4 libre dans la moitie basse.
+fn free_user_page(slot: u64) -> Page {
+    let index = (1..256)
+        .filter(|index| walk::translate(VirtAddr::new(index << 39)) == Translation::NotMapped { level: 4 })
+        .nth(slot as usize)
+        .expect("no free level 4 entry in the lower half");
+    Page::containing_address(VirtAddr::new(index << 39))
+}
+
+fn map_user_page(page: Page, flags: PageTableFlags) {
+    memory::with_kernel_memory(|memory| {
+        let frame = FrameAllocator::<Size4KiB>::allocate_frame(&mut memory.frame_allocator).unwrap();
+        unsafe {
+            memory
+                .mapper
+                .map_to(page, frame, flags, &mut memory.frame_allocator)
+                .unwrap()
+                .flush()
+        };
+    })
+    .unwrap();
+}
+
+#[test_case]
+fn protections_are_enabled() {
+    // chaque protection annoncee par le processeur doit etre active
+    assert_eq!(cpu::enabled_protections(), cpu::supported_protections());
+}
+
+#[test_case]
+fn copy_round_trip() {
+    let page = free_user_page(0);
+    map_user_page(page, USER_RW);
+    let addr = page.start_address() + 4090u64; // la copie traverse la fin de la page
+    let next = page + 1;
+    map_user_page(next, USER_RW);
+
+    copy_to_user(addr, b"hello user").unwrap();
+    let mut buffer = [0; 10];
+    copy_from_user(&mut buffer, addr).unwrap();
+    assert_eq!(&buffer, b"hello user");
+}
+
+#[test_case]
+fn kernel_addresses_are_rejected() {
+    let kernel = VirtAddr::new(allocator::heap_start() as u64);
+    let mut buffer = [0; 8];
+    assert_eq!(copy_from_user(&mut buffer, kernel), Err(UserCopyError::BadAddress(kernel)));
+
+    let text = VirtAddr::new(main as fn(&'static BootInfo) -> ! as usize as u64);
+    assert_eq!(copy_from_user(&mut buffer, text), Err(UserCopyError::NotAccessible(text)));
+}
+
+#[test_case]
+fn read_only_and_unmapped_pages_are_rejected() {
+    let page = free_user_page(1);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    map_user_page(page, flags);
+    let addr = page.start_address();
+
+    let mut buffer = [0; 8];
+    copy_from_user(&mut buffer, addr).unwrap();
+    assert_eq!(copy_to_user(addr, &buffer), Err(UserCopyError::NotAccessible(addr)));
+
+    let unmapped = (page + 1).start_address();
+    assert_eq!(copy_from_user(&mut buffer, unmapped), Err(UserCopyError::NotAccessible(unmapped)));
+}
@@ -0,0 +1,134 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use blog_os::allocator;
+use blog_os::memory::{
+    self,
+    vmm::{self, RegionKind, VmError, KERNEL_SPACE_START},
+    BitmapFrameAllocator,
+};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    //cargo test --test virtual_memory
+    blog_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BitmapFrameAllocator::init(&boot_info.memory_map, phys_mem_offset)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    blog_os::hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+const FLAGS: PageTableFlags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+
+#[test_case]
+fn heap_region_is_recorded() {
+    let heap_start = VirtAddr::new(allocator::heap_start() as u64);
+    assert!(heap_start.as_u64() >= KERNEL_SPACE_START);
+    let region = vmm::with_vmm(|vmm| *vmm.find(heap_start).unwrap());
+    assert_eq!(region.kind, RegionKind::Heap);
+    assert_eq!(region.start, heap_start);
+}
+
+#[test_case]
+fn allocated_regions_do_not_overlap() {
+    vmm::with_vmm(|vmm| {
+        let a = vmm.allocate(3 * 4096, RegionKind::Stack, FLAGS).unwrap();
+        let b = vmm.allocate(100, RegionKind::Module, FLAGS).unwrap();
+        assert_eq!(b.size, 4096);
+        for (i, region) in vmm.regions().enumerate() {
+            for other in vmm.regions().skip(i + 1) {
+                assert!(region.end() <= other.start, "{:?} overlaps {:?}", region, other);
+            }
+        }
+
+        // une plage liberee peut etre reutilisee
+        vmm.release(a.start).unwrap();
+        let c = vmm.allocate(3 * 4096, RegionKind::Stack, FLAGS).unwrap();
+        assert_eq!(c.start, a.start);
+        assert_eq!(vmm.reserve(c.start, 4096, RegionKind::Mmio, FLAGS), Err(VmError::Overlap));
+
+        vmm.release(b.start).unwrap();
+        vmm.release(c.start).unwrap();
+    });
+}
+
+#[test_case]
+fn map_and_unmap_region() {
+    memory::with_kernel_memory(|memory| {
+        let free_frames = memory.frame_allocator.free_frames();
+        vmm::with_vmm(|vmm| {
+            let region = vmm.allocate(4 * 4096, RegionKind::Module, FLAGS).unwrap();
+            vmm.map_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                .unwrap();
+            assert_eq!(vmm.release(region.start), Err(VmError::StillMapped));
+
+            let words = region.size as usize / 8;
+            let memory_ptr: *mut u64 = region.start.as_mut_ptr();
+            for i in 0..words {
+                unsafe { memory_ptr.add(i).write_volatile(i as u64) };
+            }
+            for i in 0..words {
+                assert_eq!(unsafe { memory_ptr.add(i).read_volatile() }, i as u64);
+            }
+
+            unsafe {
+                vmm.unmap_region(region.start, &mut memory.mapper, &mut memory.frame_allocator)
+                    .unwrap()
+            };
+            vmm.release(region.start).unwrap();
+        });
+        // les tables de pages intermediaires creees pour la region restent allouees
+        assert!(memory.frame_allocator.free_frames() + 3 >= free_frames);
+    })
+    .unwrap();
+}
+
+//La page de garde d'une pile n'est pas mappee, et la region est rendue quand la pile est liberee.
+#[test_case]
+fn kernel_stack_has_guard_page() {
+    use blog_os::memory::stack::KernelStack;
+    use x86_64::structures::paging::{Mapper, Page, Size4KiB};
+
+    let is_mapped = |addr: VirtAddr| {
+        memory::with_kernel_memory(|memory| {
+            memory
+                .mapper
+                .translate_page(Page::<Size4KiB>::containing_address(addr))
+                .is_ok()
+        })
+        .unwrap()
+    };
+
+    let stack = KernelStack::new(2 * 4096, "test stack").unwrap();
+    let guard = stack.bottom() - 1u64;
+    assert_eq!(stack.top() - stack.bottom(), 2 * 4096);
+    assert!(is_mapped(stack.bottom()));
+    assert!(is_mapped(stack.top() - 1u64));
+    assert!(!is_mapped(guard));
+    assert_eq!(memory::stack::overflowed_stack(guard), Some("test stack"));
+    assert_eq!(memory::stack::overflowed_stack(stack.bottom()), None);
+
+    drop(stack);
+    assert!(vmm::with_vmm(|vmm| vmm.find(guard).is_none()));
+    assert_eq!(memory::stack::overflowed_stack(guard), None);
+}